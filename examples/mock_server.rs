@@ -0,0 +1,361 @@
+//! A stand-in for spin-archive.org that keeps everything in memory, for
+//! trying out uploads end-to-end without touching the real site.
+//!
+//! ```sh
+//! cargo run --example mock_server -- 4000
+//! cargo run -- upload --host http://127.0.0.1:4000 --api-key anything video.mp4
+//! ```
+//!
+//! Any API key is accepted. Every request is printed and kept, and can be
+//! read back from `GET /mock/requests`; `GET /mock/archive` lists the files
+//! that were finalized. Tag search suggests the tags of finalized files.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+
+const DEFAULT_PORT: u16 = 4000;
+
+#[derive(Default)]
+struct MockState {
+  base_url: String,
+  next_id: u64,
+  uploads: HashMap<String, PendingUpload>,
+  archive: Vec<Value>,
+  requests: Vec<Value>,
+}
+
+struct PendingUpload {
+  file_name: String,
+  md5_hash: String,
+  contents: Option<Vec<u8>>,
+  parts: BTreeMap<u64, Vec<u8>>,
+}
+
+type State = Arc<Mutex<MockState>>;
+
+#[tokio::main]
+async fn main() {
+  let port = std::env::args()
+    .nth(1)
+    .and_then(|port| port.parse().ok())
+    .unwrap_or(DEFAULT_PORT);
+
+  let (addr, server) = serve(SocketAddr::from(([127, 0, 0, 1], port)));
+
+  println!("Mock archive listening on http://{}", addr);
+  server.await;
+}
+
+/// Binds the mock archive to `addr` and returns the address it listens on,
+/// so port 0 picks a free one, together with the server to run.
+pub fn serve(addr: SocketAddr) -> (SocketAddr, impl Future<Output = ()>) {
+  let state = State::default();
+  let service_state = state.clone();
+
+  let make_service = make_service_fn(move |_| {
+    let state = service_state.clone();
+
+    async move { Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request))) }
+  });
+
+  let server = Server::bind(&addr).serve(make_service);
+  let addr = server.local_addr();
+  state.lock().unwrap().base_url = format!("http://{}", addr);
+
+  let server = async move {
+    if let Err(err) = server.await {
+      eprintln!("Mock archive stopped: {}", err);
+    }
+  };
+
+  (addr, server)
+}
+
+async fn handle(state: State, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+  let method = request.method().clone();
+  let path = request.uri().path().to_owned();
+  let query = reqwest::Url::parse(&format!("http://mock{}", request.uri()))
+    .ok()
+    .and_then(|url| {
+      url
+        .query_pairs()
+        .find(|(key, _)| key == "query")
+        .map(|(_, value)| value.into_owned())
+    })
+    .unwrap_or_default();
+  let authorized = request
+    .headers()
+    .get("authorization")
+    .and_then(|value| value.to_str().ok())
+    .filter(|value| value.len() > "Bearer ".len())
+    .is_some();
+
+  let body = match hyper::body::to_bytes(request.into_body()).await {
+    Ok(body) => body.to_vec(),
+    Err(err) => return Ok(error(StatusCode::BAD_REQUEST, &err.to_string())),
+  };
+
+  let mut state = state.lock().unwrap();
+  state.record(&method, &path, &body);
+
+  let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+  let response = match (&method, segments.as_slice()) {
+    (&Method::GET, ["mock", "requests"]) => ok(json!(state.requests)),
+    (&Method::GET, ["mock", "archive"]) => ok(json!(state.archive)),
+    (&Method::PUT, ["storage", id]) => state.store_file(id, body),
+    (&Method::PUT, ["storage", id, "parts", part_number]) => {
+      state.store_part(id, part_number, body)
+    }
+    (_, ["api", "v1", ..]) if !authorized => error(StatusCode::UNAUTHORIZED, "Missing API key"),
+    (&Method::GET, ["api", "v1", "me"]) => ok(json!({
+      "id": 1,
+      "username": "mock",
+      "role": "contributor",
+    })),
+    (&Method::GET, ["api", "v1", "tags", "search"]) => state.search_tags(&query),
+    (&Method::GET, ["api", "v1", "uploads", "multipart", id, "parts"]) => state.parts(id),
+    (&Method::POST, ["api", "v1", ..]) => match serde_json::from_slice(&body) {
+      Ok(body) => state.post(&segments[2..], body),
+      Err(err) => error(StatusCode::BAD_REQUEST, &err.to_string()),
+    },
+    _ => error(StatusCode::NOT_FOUND, "No such endpoint"),
+  };
+
+  Ok(response)
+}
+
+impl MockState {
+  fn record(&mut self, method: &Method, path: &str, body: &[u8]) {
+    let body = match serde_json::from_slice::<Value>(body) {
+      Ok(json) => json,
+      Err(_) => json!(format!("{} bytes", body.len())),
+    };
+
+    println!("{} {} {}", method, path, body);
+
+    self.requests.push(json!({
+      "method": method.as_str(),
+      "path": path,
+      "body": body,
+    }));
+  }
+
+  fn post(&mut self, endpoint: &[&str], body: Value) -> Response<Body> {
+    match endpoint {
+      ["uploads"] => self.create_upload(&body, None),
+      ["uploads", "multipart"] => {
+        let part_size = body["part_size"].as_u64().unwrap_or_default();
+        self.create_upload(&body, Some(part_size))
+      }
+      ["uploads", "checksum"] => self.check_checksums(&body),
+      ["uploads", "finalize"] => self.finalize(&body),
+      ["uploads", "multipart", "part"] => self.part_url(&body),
+      ["uploads", "multipart", "complete"] => self.complete(&body),
+      _ => error(StatusCode::NOT_FOUND, "No such endpoint"),
+    }
+  }
+
+  fn is_archived(&self, md5_hash: &str) -> bool {
+    self
+      .archive
+      .iter()
+      .any(|file| file["md5_hash"].as_str() == Some(md5_hash))
+  }
+
+  fn search_tags(&self, query: &str) -> Response<Body> {
+    let mut post_counts: BTreeMap<&str, u64> = BTreeMap::new();
+
+    for file in &self.archive {
+      for tag in file["tags"].as_str().unwrap_or_default().split_whitespace() {
+        *post_counts.entry(tag).or_default() += 1;
+      }
+    }
+
+    let mut tags: Vec<(&str, u64)> = post_counts
+      .into_iter()
+      .filter(|(name, _)| name.starts_with(query))
+      .collect();
+    tags.sort_by_key(|(_, post_count)| std::cmp::Reverse(*post_count));
+
+    let suggestions: Vec<Value> = tags
+      .into_iter()
+      .map(|(name, post_count)| json!({ "name": name, "post_count": post_count, "aliases": [] }))
+      .collect();
+
+    ok(json!(suggestions))
+  }
+
+  fn create_upload(&mut self, body: &Value, part_size: Option<u64>) -> Response<Body> {
+    let md5_hash = body["md5_hash"].as_str().unwrap_or_default().to_owned();
+
+    if self.is_archived(&md5_hash) {
+      return error(StatusCode::CONFLICT, "File has already been uploaded");
+    }
+
+    self.next_id += 1;
+    let id = self.next_id.to_string();
+
+    self.uploads.insert(
+      id.clone(),
+      PendingUpload {
+        file_name: body["file_name"].as_str().unwrap_or_default().to_owned(),
+        md5_hash,
+        contents: None,
+        parts: BTreeMap::new(),
+      },
+    );
+
+    match part_size {
+      Some(part_size) => ok(json!({ "id": id, "part_size": part_size, "parts": [] })),
+      None => ok(json!({ "id": id, "url": format!("{}/storage/{}", self.base_url, id) })),
+    }
+  }
+
+  fn check_checksums(&self, body: &Value) -> Response<Body> {
+    let checksums: Vec<&str> = body["checksums"]
+      .as_array()
+      .map(|checksums| checksums.iter().filter_map(Value::as_str).collect())
+      .unwrap_or_default();
+
+    let archived: Vec<&str> = checksums
+      .into_iter()
+      .filter(|checksum| self.is_archived(checksum))
+      .collect();
+
+    ok(json!({ "checksums": archived }))
+  }
+
+  fn store_file(&mut self, id: &str, body: Vec<u8>) -> Response<Body> {
+    match self.uploads.get_mut(id) {
+      Some(upload) => {
+        upload.contents = Some(body);
+        ok(json!({}))
+      }
+      None => error(StatusCode::NOT_FOUND, "Upload does not exist"),
+    }
+  }
+
+  fn part_url(&self, body: &Value) -> Response<Body> {
+    let id = body["id"].as_str().unwrap_or_default();
+    let part_number = body["part_number"].as_u64().unwrap_or_default();
+
+    if !self.uploads.contains_key(id) {
+      return error(StatusCode::NOT_FOUND, "Upload does not exist");
+    }
+
+    ok(json!({
+      "url": format!("{}/storage/{}/parts/{}", self.base_url, id, part_number),
+    }))
+  }
+
+  fn store_part(&mut self, id: &str, part_number: &str, body: Vec<u8>) -> Response<Body> {
+    let part_number = match part_number.parse() {
+      Ok(part_number) => part_number,
+      Err(_) => return error(StatusCode::BAD_REQUEST, "Invalid part number"),
+    };
+
+    match self.uploads.get_mut(id) {
+      Some(upload) => {
+        let etag = format!("\"{:x}\"", md5::compute(&body));
+        upload.parts.insert(part_number, body);
+
+        let mut response = ok(json!({}));
+        response.headers_mut().insert("etag", etag.parse().unwrap());
+        response
+      }
+      None => error(StatusCode::NOT_FOUND, "Upload does not exist"),
+    }
+  }
+
+  fn parts(&self, id: &str) -> Response<Body> {
+    match self.uploads.get(id) {
+      Some(upload) => {
+        let parts: Vec<Value> = upload
+          .parts
+          .iter()
+          .map(|(part_number, contents)| {
+            json!({
+              "part_number": part_number,
+              "etag": format!("\"{:x}\"", md5::compute(contents)),
+            })
+          })
+          .collect();
+
+        ok(json!({ "parts": parts }))
+      }
+      None => error(StatusCode::NOT_FOUND, "Upload does not exist"),
+    }
+  }
+
+  fn complete(&mut self, body: &Value) -> Response<Body> {
+    let id = body["id"].as_str().unwrap_or_default();
+
+    match self.uploads.get_mut(id) {
+      Some(upload) => {
+        upload.contents = Some(upload.parts.values().flatten().copied().collect());
+        ok(json!({}))
+      }
+      None => error(StatusCode::NOT_FOUND, "Upload does not exist"),
+    }
+  }
+
+  fn finalize(&mut self, body: &Value) -> Response<Body> {
+    let id = body["id"].as_str().unwrap_or_default();
+
+    let upload = match self.uploads.get(id) {
+      Some(upload) => upload,
+      None => return error(StatusCode::NOT_FOUND, "Upload does not exist"),
+    };
+
+    let contents = match &upload.contents {
+      Some(contents) => contents,
+      None => return error(StatusCode::UNPROCESSABLE_ENTITY, "File was not received"),
+    };
+
+    if format!("{:x}", md5::compute(contents)) != upload.md5_hash {
+      return error(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "File does not match its checksum",
+      );
+    }
+
+    let file = json!({
+      "id": id,
+      "file_name": upload.file_name,
+      "md5_hash": upload.md5_hash,
+      "size": contents.len(),
+      "tags": body["tags"],
+      "source": body["source"],
+      "description": body["description"],
+    });
+
+    self.uploads.remove(id);
+    self.archive.push(file);
+
+    ok(json!({ "id": id, "url": format!("{}/storage/{}", self.base_url, id) }))
+  }
+}
+
+fn ok(body: Value) -> Response<Body> {
+  respond(StatusCode::OK, body)
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+  respond(status, json!({ "error": message }))
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+  Response::builder()
+    .status(status)
+    .header("content-type", "application/json")
+    .body(Body::from(body.to_string()))
+    .unwrap()
+}
@@ -0,0 +1,219 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+
+use super::{
+  ApiClient, ApiError, Checksums, ClientOptions, CompletedPart, Config, MultipartUpload,
+  TagSuggestion, Upload, User,
+};
+
+/// Everything the app needs from the archive.
+///
+/// [`ApiClient`] talks to a real server; `FakeApi`, built for tests and with
+/// the `fake-api` feature, keeps the archive in memory.
+#[async_trait]
+pub trait ArchiveApi: Clone + std::fmt::Debug + Send + Sync + 'static {
+  fn connect(
+    config: Config,
+    options: &ClientOptions,
+    requests_per_second: f64,
+  ) -> Result<Self, anyhow::Error>;
+
+  async fn me(&self) -> Result<User, ApiError>;
+
+  async fn check_checksums(&self, checksums: &[String]) -> Result<Vec<String>, ApiError>;
+
+  async fn search_tags(&self, query: &str) -> Result<Vec<TagSuggestion>, ApiError>;
+
+  async fn create_upload(&self, path: &Path, md5_hash: &str) -> Result<Upload, ApiError>;
+
+  /// Sends the file's contents to `url` from [`ArchiveApi::create_upload`],
+  /// calling `on_progress` with the total number of bytes sent so far.
+  async fn put_file<F>(&self, path: &Path, url: &str, on_progress: F) -> Result<(), ApiError>
+  where
+    F: FnMut(u64) + Send + Sync + 'static;
+
+  async fn finalize_upload(
+    &self,
+    id: &str,
+    tags: &str,
+    source: &str,
+    description: &str,
+  ) -> Result<(), ApiError>;
+
+  async fn create_multipart(
+    &self,
+    path: &Path,
+    md5_hash: &str,
+    part_size: u64,
+  ) -> Result<MultipartUpload, ApiError>;
+
+  async fn confirmed_parts(&self, upload: &MultipartUpload)
+    -> Result<Vec<CompletedPart>, ApiError>;
+
+  /// Sends one part of `upload`, calling `on_progress` with the number of
+  /// bytes of this part sent so far.
+  async fn upload_part<F>(
+    &self,
+    upload: &MultipartUpload,
+    path: &Path,
+    part_number: u64,
+    on_progress: F,
+  ) -> Result<CompletedPart, ApiError>
+  where
+    F: FnMut(u64) + Send + Sync + 'static;
+
+  async fn complete_multipart(&self, upload: &MultipartUpload) -> Result<(), ApiError>;
+}
+
+#[async_trait]
+impl ArchiveApi for ApiClient {
+  fn connect(
+    config: Config,
+    options: &ClientOptions,
+    requests_per_second: f64,
+  ) -> Result<Self, anyhow::Error> {
+    Ok(ApiClient::new(config, options)?.with_rate_limit(requests_per_second))
+  }
+
+  async fn me(&self) -> Result<User, ApiError> {
+    User::get(self).await
+  }
+
+  async fn check_checksums(&self, checksums: &[String]) -> Result<Vec<String>, ApiError> {
+    Checksums::check(checksums, self)
+      .await
+      .map(|response| response.checksums)
+  }
+
+  async fn search_tags(&self, query: &str) -> Result<Vec<TagSuggestion>, ApiError> {
+    TagSuggestion::search(self, query).await
+  }
+
+  async fn create_upload(&self, path: &Path, md5_hash: &str) -> Result<Upload, ApiError> {
+    Upload::new(self, path, md5_hash).await
+  }
+
+  async fn put_file<F>(&self, path: &Path, url: &str, on_progress: F) -> Result<(), ApiError>
+  where
+    F: FnMut(u64) + Send + Sync + 'static,
+  {
+    Upload::upload_file(self, path, url, on_progress).await
+  }
+
+  async fn finalize_upload(
+    &self,
+    id: &str,
+    tags: &str,
+    source: &str,
+    description: &str,
+  ) -> Result<(), ApiError> {
+    Upload::finalize(self, id, tags, source, description)
+      .await
+      .map(|_| ())
+  }
+
+  async fn create_multipart(
+    &self,
+    path: &Path,
+    md5_hash: &str,
+    part_size: u64,
+  ) -> Result<MultipartUpload, ApiError> {
+    MultipartUpload::new(self, path, md5_hash, part_size).await
+  }
+
+  async fn confirmed_parts(
+    &self,
+    upload: &MultipartUpload,
+  ) -> Result<Vec<CompletedPart>, ApiError> {
+    upload.confirmed_parts(self).await
+  }
+
+  async fn upload_part<F>(
+    &self,
+    upload: &MultipartUpload,
+    path: &Path,
+    part_number: u64,
+    on_progress: F,
+  ) -> Result<CompletedPart, ApiError>
+  where
+    F: FnMut(u64) + Send + Sync + 'static,
+  {
+    upload
+      .upload_part(self, path, part_number, on_progress)
+      .await
+  }
+
+  async fn complete_multipart(&self, upload: &MultipartUpload) -> Result<(), ApiError> {
+    upload.complete(self).await
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::Value;
+
+  use super::super::mock_server;
+  use super::*;
+
+  #[tokio::test]
+  async fn uploads_to_the_mock_server() {
+    let (addr, server) = mock_server::serve(([127, 0, 0, 1], 0).into());
+    tokio::spawn(server);
+
+    let host = format!("http://{}", addr);
+    let config = Config::new(host.clone(), "anything".to_owned());
+    let client = ApiClient::connect(config, &ClientOptions::default(), 0.0).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("clip.mp4");
+    std::fs::write(&path, b"not really a video").unwrap();
+    let md5_hash = format!("{:x}", md5::compute(b"not really a video"));
+    let checksums = vec![md5_hash.clone()];
+
+    assert_eq!(client.me().await.unwrap().username, "mock");
+    assert!(client.check_checksums(&checksums).await.unwrap().is_empty());
+
+    let upload = client.create_upload(&path, &md5_hash).await.unwrap();
+    client.put_file(&path, &upload.url, |_| {}).await.unwrap();
+    client
+      .finalize_upload(&upload.id, "spin", "", "")
+      .await
+      .unwrap();
+
+    assert_eq!(client.check_checksums(&checksums).await.unwrap(), checksums);
+
+    let requests: Vec<Value> = reqwest::get(&format!("{}/mock/requests", host))
+      .await
+      .unwrap()
+      .json()
+      .await
+      .unwrap();
+    let calls: Vec<(&str, &str)> = requests
+      .iter()
+      .map(|request| {
+        (
+          request["method"].as_str().unwrap(),
+          request["path"].as_str().unwrap(),
+        )
+      })
+      .collect();
+
+    assert_eq!(
+      calls,
+      vec![
+        ("GET", "/api/v1/me"),
+        ("POST", "/api/v1/uploads/checksum"),
+        ("POST", "/api/v1/uploads"),
+        ("PUT", "/storage/1"),
+        ("POST", "/api/v1/uploads/finalize"),
+        ("POST", "/api/v1/uploads/checksum"),
+        ("GET", "/mock/requests"),
+      ]
+    );
+    assert_eq!(requests[2]["body"]["md5_hash"], md5_hash.as_str());
+    assert_eq!(requests[2]["body"]["file_name"], "clip.mp4");
+    assert_eq!(requests[3]["body"], "18 bytes");
+    assert_eq!(requests[4]["body"]["tags"], "spin");
+  }
+}
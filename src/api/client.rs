@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, Proxy, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use super::limiter::{self, RateLimiter};
+use super::Config;
+
+const USER_AGENT: &str = concat!("mx/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ClientOptions {
+  /// Proxy for all requests, e.g. `http://proxy.local:3128`.
+  pub proxy: Option<String>,
+  pub ca_bundle: Option<PathBuf>,
+  pub connect_timeout_secs: u64,
+  /// Limit for API calls. Transfers of file contents have none, as large
+  /// files can take hours.
+  pub request_timeout_secs: u64,
+}
+
+impl Default for ClientOptions {
+  fn default() -> Self {
+    Self {
+      proxy: None,
+      ca_bundle: None,
+      connect_timeout_secs: 10,
+      request_timeout_secs: 30,
+    }
+  }
+}
+
+/// Talks to one server, reusing a single connection pool and rate limit for
+/// every request made through it or its clones.
+#[derive(Debug, Clone)]
+pub struct ApiClient {
+  config: Config,
+  http: Client,
+  limiter: Arc<RateLimiter>,
+  request_timeout: Duration,
+}
+
+impl ApiClient {
+  pub fn new(config: Config, options: &ClientOptions) -> Result<Self, anyhow::Error> {
+    let mut builder = Client::builder()
+      .user_agent(USER_AGENT)
+      .connect_timeout(Duration::from_secs(options.connect_timeout_secs))
+      .pool_max_idle_per_host(5);
+
+    if let Some(proxy) = &options.proxy {
+      builder = builder.proxy(Proxy::all(proxy.as_str())?);
+    }
+
+    if let Some(path) = &options.ca_bundle {
+      for certificate in read_certificates(path)? {
+        builder = builder.add_root_certificate(certificate);
+      }
+    }
+
+    Ok(Self {
+      config,
+      http: builder.build()?,
+      limiter: Arc::new(RateLimiter::new(limiter::DEFAULT_REQUESTS_PER_SECOND)),
+      request_timeout: Duration::from_secs(options.request_timeout_secs),
+    })
+  }
+
+  pub fn with_rate_limit(mut self, requests_per_second: f64) -> Self {
+    self.limiter = Arc::new(RateLimiter::new(requests_per_second));
+    self
+  }
+
+  pub(super) fn request(&self, method: reqwest::Method, path: &str) -> RequestBuilder {
+    self
+      .http
+      .request(method, &format!("{}{}", self.config.host, path))
+      .timeout(self.request_timeout)
+      .header("content-type", "application/json")
+      .header("authorization", format!("Bearer {}", self.config.api_token))
+  }
+
+  /// Starts a PUT of file contents to a presigned storage URL.
+  ///
+  /// These skip the rate limit: they go to storage rather than the API, and
+  /// each follows an API call that already waited for its turn.
+  pub(super) fn put(&self, url: &str) -> RequestBuilder {
+    self.http.put(url)
+  }
+
+  /// Sends an API request as soon as the rate limit allows, backing off
+  /// every request when the server answers `429 Too Many Requests`.
+  pub(super) async fn send(&self, request: RequestBuilder) -> Result<Response, reqwest::Error> {
+    self.limiter.acquire().await;
+
+    let response = request.send().await;
+
+    if let Ok(response) = &response {
+      if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        self.limiter.back_off(limiter::retry_after(response));
+      }
+    }
+
+    response
+  }
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<Certificate>, anyhow::Error> {
+  const BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+
+  let bundle = std::fs::read_to_string(path)?;
+  let certificates = bundle
+    .split(BEGIN)
+    .skip(1)
+    .map(|pem| Certificate::from_pem(format!("{}{}", BEGIN, pem).as_bytes()))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  if certificates.is_empty() {
+    anyhow::bail!("no certificates found in {}", path.display());
+  }
+
+  Ok(certificates)
+}
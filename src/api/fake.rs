@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::delay_for;
+
+use super::{
+  ApiError, ArchiveApi, ClientOptions, CompletedPart, Config, MultipartUpload, TagSuggestion,
+  Upload, User,
+};
+
+/// Delay before every call, in milliseconds.
+const LATENCY_VAR: &str = "MX_FAKE_LATENCY_MS";
+/// Fail every n-th call with a server error.
+const FAIL_EVERY_VAR: &str = "MX_FAKE_FAIL_EVERY";
+
+const DEFAULT_LATENCY: Duration = Duration::from_millis(200);
+
+const PROGRESS_STEPS: u64 = 10;
+
+const INITIAL_TAGS: &[(&str, u64, &[&str])] = &[
+  ("spinning", 5120, &["spin"]),
+  ("long_hair", 1830, &["longhair"]),
+  ("outdoors", 960, &["outside"]),
+  ("music", 740, &[]),
+  ("slow_motion", 310, &["slowmo"]),
+];
+
+const MAX_SUGGESTIONS: usize = 10;
+
+/// An archive kept in memory, to try out the app without a server.
+///
+/// Every call waits for `latency` first and every `fail_every`-th call fails
+/// with a server error, to see how the app copes with slow or flaky servers.
+#[derive(Debug, Clone, Default)]
+pub struct FakeApi {
+  latency: Duration,
+  fail_every: Option<u64>,
+  state: Arc<Mutex<FakeState>>,
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+  calls_since_failure: u64,
+  next_id: u64,
+  archived: HashSet<String>,
+  uploads: HashMap<String, FakeUpload>,
+  tags: BTreeMap<String, u64>,
+}
+
+#[derive(Debug)]
+struct FakeUpload {
+  md5_hash: String,
+  size: u64,
+  received: bool,
+  parts: Vec<CompletedPart>,
+}
+
+impl FakeApi {
+  pub fn new(latency: Duration, fail_every: Option<u64>) -> Self {
+    Self {
+      latency,
+      fail_every,
+      state: Arc::default(),
+    }
+  }
+
+  async fn call(&self) -> Result<(), ApiError> {
+    delay_for(self.latency).await;
+
+    let mut state = self.state.lock().unwrap();
+    state.calls_since_failure += 1;
+
+    match self.fail_every {
+      Some(fail_every) if state.calls_since_failure >= fail_every => {
+        state.calls_since_failure = 0;
+        Err(ApiError::ServerError(
+          503,
+          Some("Injected failure".to_owned()),
+        ))
+      }
+      _ => Ok(()),
+    }
+  }
+
+  fn new_upload(&self, path: &Path, md5_hash: &str) -> Result<String, ApiError> {
+    let size = std::fs::metadata(path)?.len();
+    let mut state = self.state.lock().unwrap();
+
+    if state.archived.contains(md5_hash) {
+      return Err(ApiError::Conflict(Some(
+        "File has already been uploaded".to_owned(),
+      )));
+    }
+
+    state.next_id += 1;
+    let id = state.next_id.to_string();
+
+    state.uploads.insert(
+      id.clone(),
+      FakeUpload {
+        md5_hash: md5_hash.to_owned(),
+        size,
+        received: false,
+        parts: Vec::new(),
+      },
+    );
+
+    Ok(id)
+  }
+
+  async fn transfer<F>(&self, length: u64, mut on_progress: F)
+  where
+    F: FnMut(u64) + Send + Sync + 'static,
+  {
+    let step = (length / PROGRESS_STEPS).max(1);
+    let mut bytes_sent = 0;
+
+    while bytes_sent < length {
+      delay_for(self.latency / PROGRESS_STEPS as u32).await;
+      bytes_sent = (bytes_sent + step).min(length);
+      on_progress(bytes_sent);
+    }
+  }
+}
+
+fn unknown_upload() -> ApiError {
+  ApiError::NotFound(Some("Upload does not exist".to_owned()))
+}
+
+#[async_trait]
+impl ArchiveApi for FakeApi {
+  /// Ignores the server settings and reads the latency and failure rate from
+  /// `MX_FAKE_LATENCY_MS` and `MX_FAKE_FAIL_EVERY` instead.
+  fn connect(
+    _config: Config,
+    _options: &ClientOptions,
+    _requests_per_second: f64,
+  ) -> Result<Self, anyhow::Error> {
+    let latency = match std::env::var(LATENCY_VAR) {
+      Ok(value) => Duration::from_millis(value.parse()?),
+      Err(_) => DEFAULT_LATENCY,
+    };
+
+    let fail_every = match std::env::var(FAIL_EVERY_VAR) {
+      Ok(value) => Some(value.parse()?),
+      Err(_) => None,
+    };
+
+    Ok(Self::new(latency, fail_every))
+  }
+
+  async fn me(&self) -> Result<User, ApiError> {
+    self.call().await?;
+
+    Ok(User {
+      id: 1,
+      username: "demo".to_owned(),
+      role: "contributor".to_owned(),
+    })
+  }
+
+  async fn check_checksums(&self, checksums: &[String]) -> Result<Vec<String>, ApiError> {
+    self.call().await?;
+
+    let state = self.state.lock().unwrap();
+
+    Ok(
+      checksums
+        .iter()
+        .filter(|checksum| state.archived.contains(*checksum))
+        .cloned()
+        .collect(),
+    )
+  }
+
+  async fn search_tags(&self, query: &str) -> Result<Vec<TagSuggestion>, ApiError> {
+    self.call().await?;
+
+    let state = self.state.lock().unwrap();
+
+    let mut suggestions: Vec<TagSuggestion> = INITIAL_TAGS
+      .iter()
+      .map(|(name, post_count, aliases)| TagSuggestion {
+        name: (*name).to_owned(),
+        post_count: post_count + state.tags.get(*name).copied().unwrap_or_default(),
+        aliases: aliases.iter().map(|alias| (*alias).to_owned()).collect(),
+      })
+      .collect();
+
+    for (name, post_count) in &state.tags {
+      if !suggestions
+        .iter()
+        .any(|suggestion| &suggestion.name == name)
+      {
+        suggestions.push(TagSuggestion {
+          name: name.clone(),
+          post_count: *post_count,
+          aliases: Vec::new(),
+        });
+      }
+    }
+
+    suggestions.retain(|suggestion| {
+      suggestion.name.starts_with(query)
+        || suggestion
+          .aliases
+          .iter()
+          .any(|alias| alias.starts_with(query))
+    });
+    suggestions.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.post_count));
+    suggestions.truncate(MAX_SUGGESTIONS);
+
+    Ok(suggestions)
+  }
+
+  async fn create_upload(&self, path: &Path, md5_hash: &str) -> Result<Upload, ApiError> {
+    self.call().await?;
+
+    let id = self.new_upload(path, md5_hash)?;
+
+    Ok(Upload {
+      url: format!("fake://uploads/{}", id),
+      id,
+    })
+  }
+
+  async fn put_file<F>(&self, _path: &Path, url: &str, on_progress: F) -> Result<(), ApiError>
+  where
+    F: FnMut(u64) + Send + Sync + 'static,
+  {
+    self.call().await?;
+
+    let id = url.rsplit('/').next().unwrap_or_default();
+    let size = match self.state.lock().unwrap().uploads.get(id) {
+      Some(upload) => upload.size,
+      None => return Err(unknown_upload()),
+    };
+
+    self.transfer(size, on_progress).await;
+
+    if let Some(upload) = self.state.lock().unwrap().uploads.get_mut(id) {
+      upload.received = true;
+    }
+
+    Ok(())
+  }
+
+  async fn finalize_upload(
+    &self,
+    id: &str,
+    tags: &str,
+    _source: &str,
+    _description: &str,
+  ) -> Result<(), ApiError> {
+    self.call().await?;
+
+    let mut state = self.state.lock().unwrap();
+
+    let md5_hash = match state.uploads.get(id) {
+      Some(upload) if upload.received => upload.md5_hash.clone(),
+      Some(_) => {
+        return Err(ApiError::Unprocessable(Some(
+          "File was not received".to_owned(),
+        )))
+      }
+      None => return Err(unknown_upload()),
+    };
+
+    state.uploads.remove(id);
+    state.archived.insert(md5_hash);
+
+    for tag in tags.split_whitespace() {
+      *state.tags.entry(tag.to_owned()).or_default() += 1;
+    }
+
+    Ok(())
+  }
+
+  async fn create_multipart(
+    &self,
+    path: &Path,
+    md5_hash: &str,
+    part_size: u64,
+  ) -> Result<MultipartUpload, ApiError> {
+    self.call().await?;
+
+    Ok(MultipartUpload {
+      id: self.new_upload(path, md5_hash)?,
+      part_size,
+      parts: Vec::new(),
+    })
+  }
+
+  async fn confirmed_parts(
+    &self,
+    upload: &MultipartUpload,
+  ) -> Result<Vec<CompletedPart>, ApiError> {
+    self.call().await?;
+
+    match self.state.lock().unwrap().uploads.get(&upload.id) {
+      Some(fake) => Ok(fake.parts.clone()),
+      None => Err(unknown_upload()),
+    }
+  }
+
+  async fn upload_part<F>(
+    &self,
+    upload: &MultipartUpload,
+    _path: &Path,
+    part_number: u64,
+    on_progress: F,
+  ) -> Result<CompletedPart, ApiError>
+  where
+    F: FnMut(u64) + Send + Sync + 'static,
+  {
+    self.call().await?;
+
+    let size = match self.state.lock().unwrap().uploads.get(&upload.id) {
+      Some(fake) => fake.size,
+      None => return Err(unknown_upload()),
+    };
+
+    self
+      .transfer(upload.part_length(part_number, size), on_progress)
+      .await;
+
+    let part = CompletedPart {
+      part_number,
+      etag: format!("\"{}-{}\"", upload.id, part_number),
+    };
+
+    if let Some(fake) = self.state.lock().unwrap().uploads.get_mut(&upload.id) {
+      fake.parts.retain(|done| done.part_number != part_number);
+      fake.parts.push(part.clone());
+    }
+
+    Ok(part)
+  }
+
+  async fn complete_multipart(&self, upload: &MultipartUpload) -> Result<(), ApiError> {
+    self.call().await?;
+
+    let mut state = self.state.lock().unwrap();
+
+    match state.uploads.get_mut(&upload.id) {
+      Some(fake) if fake.parts.len() as u64 == upload.part_count(fake.size) => {
+        fake.received = true;
+        Ok(())
+      }
+      Some(_) => Err(ApiError::Unprocessable(Some(
+        "Not all parts were received".to_owned(),
+      ))),
+      None => Err(unknown_upload()),
+    }
+  }
+}
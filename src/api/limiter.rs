@@ -0,0 +1,181 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::RETRY_AFTER;
+use reqwest::Response;
+use tokio::time::{delay_for, Instant};
+
+pub const DEFAULT_REQUESTS_PER_SECOND: f64 = 5.0;
+
+/// How long to back off after a `429` without a usable `Retry-After`.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Spaces out API requests to stay within a requests-per-second budget, and
+/// holds all of them back while the server asks us to slow down.
+#[derive(Debug)]
+pub struct RateLimiter {
+  interval: Duration,
+  next_request: Mutex<Instant>,
+}
+
+impl RateLimiter {
+  /// A budget of zero or less disables the limit.
+  pub fn new(requests_per_second: f64) -> Self {
+    let interval = if requests_per_second > 0.0 {
+      Duration::from_secs_f64(1.0 / requests_per_second)
+    } else {
+      Duration::from_secs(0)
+    };
+
+    Self {
+      interval,
+      next_request: Mutex::new(Instant::now()),
+    }
+  }
+
+  pub async fn acquire(&self) {
+    let wait = {
+      let mut next_request = self.next_request.lock().unwrap();
+      let now = Instant::now();
+      let slot = (*next_request).max(now);
+
+      *next_request = slot + self.interval;
+      slot - now
+    };
+
+    if wait > Duration::from_secs(0) {
+      delay_for(wait).await;
+    }
+  }
+
+  pub fn back_off(&self, delay: Duration) {
+    let mut next_request = self.next_request.lock().unwrap();
+    *next_request = (*next_request).max(Instant::now() + delay);
+  }
+}
+
+/// How long a `429` response asks us to wait, from a `Retry-After` in
+/// seconds or as a date.
+pub fn retry_after(response: &Response) -> Duration {
+  let value = match response
+    .headers()
+    .get(RETRY_AFTER)
+    .and_then(|value| value.to_str().ok())
+  {
+    Some(value) => value.trim(),
+    None => return DEFAULT_RETRY_AFTER,
+  };
+
+  if let Ok(seconds) = value.parse() {
+    return Duration::from_secs(seconds);
+  }
+
+  match httpdate::parse_http_date(value) {
+    // A date in the past means right away.
+    Ok(date) => date
+      .duration_since(SystemTime::now())
+      .unwrap_or_else(|_| Duration::from_secs(0)),
+    Err(_) => DEFAULT_RETRY_AFTER,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn response(retry_after: Option<&str>) -> Response {
+    let mut builder = hyper::Response::builder().status(429);
+
+    if let Some(retry_after) = retry_after {
+      builder = builder.header(RETRY_AFTER, retry_after);
+    }
+
+    Response::from(builder.body("").unwrap())
+  }
+
+  /// Timers round up to the next millisecond, hence the slack.
+  fn assert_waited(start: Instant, expected: Duration) {
+    let waited = start.elapsed();
+    assert!(
+      waited >= expected && waited < expected + Duration::from_millis(10),
+      "waited {:?} instead of {:?}",
+      waited,
+      expected
+    );
+  }
+
+  #[tokio::test]
+  async fn spaces_out_requests() {
+    tokio::time::pause();
+    let limiter = RateLimiter::new(4.0);
+    let start = Instant::now();
+
+    for _ in 0..5 {
+      limiter.acquire().await;
+    }
+
+    // The first request goes out right away, the others 250 ms apart.
+    assert_waited(start, Duration::from_secs(1));
+  }
+
+  #[tokio::test]
+  async fn zero_budget_is_unlimited() {
+    tokio::time::pause();
+    let limiter = RateLimiter::new(0.0);
+    let start = Instant::now();
+
+    for _ in 0..100 {
+      limiter.acquire().await;
+    }
+
+    assert_waited(start, Duration::from_secs(0));
+  }
+
+  #[tokio::test]
+  async fn backs_off_every_request() {
+    tokio::time::pause();
+    let limiter = RateLimiter::new(10.0);
+    let start = Instant::now();
+
+    limiter.acquire().await;
+    limiter.back_off(retry_after(&response(Some("3"))));
+    limiter.acquire().await;
+    assert_waited(start, Duration::from_secs(3));
+
+    // A shorter back-off does not shorten the wait already asked for.
+    limiter.back_off(Duration::from_secs(5));
+    limiter.back_off(Duration::from_secs(1));
+    limiter.acquire().await;
+    assert_waited(start, Duration::from_secs(8));
+  }
+
+  #[test]
+  fn reads_retry_after_seconds() {
+    assert_eq!(
+      retry_after(&response(Some("120"))),
+      Duration::from_secs(120)
+    );
+    assert_eq!(retry_after(&response(Some(" 0 "))), Duration::from_secs(0));
+  }
+
+  #[test]
+  fn reads_retry_after_dates() {
+    let later = SystemTime::now() + Duration::from_secs(120);
+    let wait = retry_after(&response(Some(&httpdate::fmt_http_date(later))));
+    // The date only has whole seconds.
+    assert!(wait > Duration::from_secs(118) && wait <= Duration::from_secs(120));
+
+    assert_eq!(
+      retry_after(&response(Some("Wed, 21 Oct 2015 07:28:00 GMT"))),
+      Duration::from_secs(0)
+    );
+  }
+
+  #[test]
+  fn falls_back_without_a_usable_retry_after() {
+    assert_eq!(retry_after(&response(None)), DEFAULT_RETRY_AFTER);
+    assert_eq!(retry_after(&response(Some("soon"))), DEFAULT_RETRY_AFTER);
+    assert_eq!(retry_after(&response(Some("-5"))), DEFAULT_RETRY_AFTER);
+    assert_eq!(retry_after(&response(Some(""))), DEFAULT_RETRY_AFTER);
+  }
+}
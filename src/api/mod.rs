@@ -1,15 +1,34 @@
-use std::path::PathBuf;
-use std::time::Duration;
+use std::io::SeekFrom;
+use std::path::Path;
 
-use reqwest::{Body, Client, Response, StatusCode};
+use futures::StreamExt;
+use reqwest::{Body, Method, Response, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use thiserror::Error;
 use tokio::fs::File;
-use tokio::time::delay_for;
+use tokio::io::AsyncReadExt;
 use tokio_util::codec::{BytesCodec, FramedRead};
 
+mod archive;
+mod client;
+#[cfg(any(test, feature = "fake-api"))]
+mod fake;
+mod limiter;
+// The example server, so the client is tested against what people try it
+// out with.
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../../examples/mock_server.rs"]
+mod mock_server;
+
+pub use archive::ArchiveApi;
+pub use client::{ApiClient, ClientOptions};
+#[cfg(any(test, feature = "fake-api"))]
+pub use fake::FakeApi;
+pub use limiter::DEFAULT_REQUESTS_PER_SECOND;
+
 /// Configuration used for making API requests.
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -18,31 +37,107 @@ pub struct Config {
 }
 
 impl Config {
-  pub fn new(api_token: String) -> Self {
+  pub fn new(host: String, api_token: String) -> Self {
     Self {
       api_token,
-      host: "https://spin-archive.org".to_owned(),
+      host: host.trim_end_matches('/').to_owned(),
     }
   }
 }
 
-fn client() -> Client {
-  Client::builder().pool_max_idle_per_host(5).build().unwrap()
-}
-
-#[derive(Error, Debug)]
+/// Why a request failed. HTTP errors carry the message the server sent
+/// along, if any.
+#[derive(Error, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ApiError {
-  #[error("Resource was not found")]
-  NotFound,
+  #[error("API key was not valid{}", reason(.0))]
+  Unauthorized(Option<String>),
+
+  #[error("Not allowed{}", reason(.0))]
+  Forbidden(Option<String>),
+
+  #[error("Resource was not found{}", reason(.0))]
+  NotFound(Option<String>),
+
+  #[error("Conflicts with an existing upload{}", reason(.0))]
+  Conflict(Option<String>),
+
+  #[error("File is too large{}", reason(.0))]
+  PayloadTooLarge(Option<String>),
+
+  #[error("Rejected by the server{}", reason(.0))]
+  Unprocessable(Option<String>),
+
+  #[error("Too many requests{}", reason(.0))]
+  RateLimited(Option<String>),
+
+  #[error("Server error {0}{}", reason(.1))]
+  ServerError(u16, Option<String>),
 
-  #[error("API key was not valid")]
-  ApiKeyError,
+  #[error("Server responded with status {0}{}", reason(.1))]
+  HttpError(u16, Option<String>),
+
+  #[error("Request timed out")]
+  Timeout,
 
   #[error("Error decoding JSON")]
   JsonError,
 
   #[error("Server is unreachable at this time")]
   ServerUnavailable,
+
+  #[error("Could not read file: {0}")]
+  FileError(String),
+
+  #[error("Unexpected response from the server: {0}")]
+  Protocol(String),
+}
+
+fn reason(message: &Option<String>) -> String {
+  match message {
+    Some(message) => format!(": {}", message),
+    None => String::new(),
+  }
+}
+
+impl ApiError {
+  /// Whether the request may succeed if it is simply tried again later.
+  pub fn is_transient(&self) -> bool {
+    matches!(
+      self,
+      ApiError::ServerUnavailable
+        | ApiError::ServerError(..)
+        | ApiError::Timeout
+        | ApiError::RateLimited(_)
+    )
+  }
+
+  pub fn advice(&self) -> &'static str {
+    match self {
+      ApiError::Unauthorized(_) | ApiError::Forbidden(_) => "check the API key",
+      ApiError::Unprocessable(_) => "fix the tags and retry",
+      ApiError::Conflict(_) => "already on the archive",
+      ApiError::PayloadTooLarge(_) => "file is over the size limit",
+      ApiError::FileError(_) => "check the file",
+      error if error.is_transient() => "retry later",
+      _ => "retry",
+    }
+  }
+}
+
+impl From<reqwest::Error> for ApiError {
+  fn from(error: reqwest::Error) -> Self {
+    if error.is_timeout() {
+      ApiError::Timeout
+    } else {
+      ApiError::ServerUnavailable
+    }
+  }
+}
+
+impl From<std::io::Error> for ApiError {
+  fn from(error: std::io::Error) -> Self {
+    ApiError::FileError(error.to_string())
+  }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -53,15 +148,10 @@ pub struct User {
 }
 
 impl User {
-  pub async fn get(config: &Config) -> Result<Self, ApiError> {
-    let endpoint = format!("{}/api/v1/me", config.host);
+  pub async fn get(client: &ApiClient) -> Result<Self, ApiError> {
+    let request = client.request(Method::GET, "/api/v1/me");
 
-    let response = client()
-      .get(&endpoint)
-      .header("content-type", "application/json")
-      .header("authorization", format!("Bearer {}", config.api_token))
-      .send()
-      .await;
+    let response = client.send(request).await;
 
     handle_response(response).await
   }
@@ -73,16 +163,33 @@ pub struct Checksums {
 }
 
 impl Checksums {
-  pub async fn check(checksums: &Vec<String>, config: &Config) -> Result<Self, ApiError> {
-    let endpoint = format!("{}/api/v1/uploads/checksum", config.host);
-
-    let response = client()
-      .post(&endpoint)
-      .header("content-type", "application/json")
-      .header("authorization", format!("Bearer {}", config.api_token))
-      .json(&json!({ "checksums": checksums }))
-      .send()
-      .await;
+  pub async fn check(checksums: &[String], client: &ApiClient) -> Result<Self, ApiError> {
+    let request = client
+      .request(Method::POST, "/api/v1/uploads/checksum")
+      .json(&json!({ "checksums": checksums }));
+
+    let response = client.send(request).await;
+
+    handle_response(response).await
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TagSuggestion {
+  pub name: String,
+  pub post_count: u64,
+  /// Other names that resolve to this tag.
+  #[serde(default)]
+  pub aliases: Vec<String>,
+}
+
+impl TagSuggestion {
+  pub async fn search(client: &ApiClient, query: &str) -> Result<Vec<Self>, ApiError> {
+    let request = client
+      .request(Method::GET, "/api/v1/tags/search")
+      .query(&[("query", query)]);
+
+    let response = client.send(request).await;
 
     handle_response(response).await
   }
@@ -95,55 +202,57 @@ pub struct Upload {
 }
 
 impl Upload {
-  pub async fn new(config: &Config, path: &PathBuf, md5_hash: &str) -> Result<Self, ApiError> {
-    let endpoint = format!("{}/api/v1/uploads", config.host);
-
-    let metadata = std::fs::metadata(&path).unwrap();
+  pub async fn new(client: &ApiClient, path: &Path, md5_hash: &str) -> Result<Self, ApiError> {
+    let metadata = std::fs::metadata(path)?;
     let file_name = path.file_name().unwrap().to_str().unwrap();
     let file_size = metadata.len() as i64;
 
-    let _ = delay_for(Duration::from_millis(100)).await;
-
     let new_upload_request = json!({
       "file_name": file_name,
       "content_length": file_size,
       "md5_hash": md5_hash,
     });
 
-    let response = client()
-      .post(&endpoint)
-      .header("content-type", "application/json")
-      .header("authorization", format!("Bearer {}", config.api_token))
-      .json(&new_upload_request)
-      .send()
-      .await;
+    let request = client
+      .request(Method::POST, "/api/v1/uploads")
+      .json(&new_upload_request);
+
+    let response = client.send(request).await;
 
     handle_response(response).await
   }
 
-  pub async fn upload_file(path: &PathBuf, url: &str) -> Result<(), ApiError> {
-    let file = File::open(path).await.unwrap();
-    let stream = FramedRead::new(file, BytesCodec::new());
+  pub async fn upload_file<F>(
+    client: &ApiClient,
+    path: &Path,
+    url: &str,
+    mut on_progress: F,
+  ) -> Result<(), ApiError>
+  where
+    F: FnMut(u64) + Send + Sync + 'static,
+  {
+    let file = File::open(path).await?;
+    let mut bytes_sent = 0;
+    let stream = FramedRead::new(file, BytesCodec::new()).inspect(move |chunk| {
+      if let Ok(chunk) = chunk {
+        bytes_sent += chunk.len() as u64;
+        on_progress(bytes_sent);
+      }
+    });
     let body = Body::wrap_stream(stream);
 
-    let _ = delay_for(Duration::from_millis(100)).await;
-
-    let response = client().put(url).body(body).send().await;
+    let response = client.put(url).body(body).send().await;
 
-    response
-      .map_err(|_| ApiError::ServerUnavailable)
-      .map(|_| ())
+    handle_put_response(response).await.map(|_| ())
   }
 
   pub async fn finalize(
-    config: &Config,
+    client: &ApiClient,
     id: &str,
     tags: &str,
     source: &str,
     description: &str,
   ) -> Result<Self, ApiError> {
-    let endpoint = format!("{}/api/v1/uploads/finalize", config.host);
-
     let finalize_request = json!({
       "id": id,
       "tags": tags,
@@ -151,41 +260,379 @@ impl Upload {
       "description": description,
     });
 
-    let _ = delay_for(Duration::from_millis(100)).await;
+    let request = client
+      .request(Method::POST, "/api/v1/uploads/finalize")
+      .json(&finalize_request);
+
+    let response = client.send(request).await;
+
+    handle_response(response).await
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompletedPart {
+  pub part_number: u64,
+  pub etag: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PartUrl {
+  url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CompletedParts {
+  parts: Vec<CompletedPart>,
+}
+
+/// An upload sent in fixed-size parts, so an interrupted transfer can resume
+/// from the last confirmed part instead of starting over.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultipartUpload {
+  pub id: String,
+  pub part_size: u64,
+  #[serde(default)]
+  pub parts: Vec<CompletedPart>,
+}
+
+impl MultipartUpload {
+  pub async fn new(
+    client: &ApiClient,
+    path: &Path,
+    md5_hash: &str,
+    part_size: u64,
+  ) -> Result<Self, ApiError> {
+    let metadata = std::fs::metadata(path)?;
+    let file_name = path.file_name().unwrap().to_str().unwrap();
+    let file_size = metadata.len() as i64;
+
+    let new_upload_request = json!({
+      "file_name": file_name,
+      "content_length": file_size,
+      "md5_hash": md5_hash,
+      "part_size": part_size,
+    });
+
+    let request = client
+      .request(Method::POST, "/api/v1/uploads/multipart")
+      .json(&new_upload_request);
+
+    let response = client.send(request).await;
+
+    handle_response(response).await
+  }
+
+  pub fn part_count(&self, file_size: u64) -> u64 {
+    if self.part_size == 0 {
+      return 0;
+    }
+
+    let full_parts = file_size / self.part_size;
+    let remainder = file_size % self.part_size;
+
+    if remainder > 0 {
+      full_parts + 1
+    } else {
+      full_parts
+    }
+  }
+
+  /// Length of part `part_number` of a file of `file_size` bytes. Only the
+  /// last part is shorter than `part_size`.
+  pub fn part_length(&self, part_number: u64, file_size: u64) -> u64 {
+    let offset = part_number.saturating_sub(1) * self.part_size;
+    self.part_size.min(file_size.saturating_sub(offset))
+  }
+
+  /// Bytes of a file of `file_size` bytes in the parts confirmed so far,
+  /// which need not be the first ones.
+  pub fn confirmed_bytes(&self, file_size: u64) -> u64 {
+    self
+      .parts
+      .iter()
+      .map(|part| self.part_length(part.part_number, file_size))
+      .sum()
+  }
+
+  pub async fn confirmed_parts(&self, client: &ApiClient) -> Result<Vec<CompletedPart>, ApiError> {
+    let request = client.request(
+      Method::GET,
+      &format!("/api/v1/uploads/multipart/{}/parts", self.id),
+    );
+
+    let response = client.send(request).await;
+
+    handle_response::<CompletedParts>(response)
+      .await
+      .map(|response| response.parts)
+  }
+
+  pub async fn upload_part<F>(
+    &self,
+    client: &ApiClient,
+    path: &Path,
+    part_number: u64,
+    mut on_progress: F,
+  ) -> Result<CompletedPart, ApiError>
+  where
+    F: FnMut(u64) + Send + Sync + 'static,
+  {
+    let request = client
+      .request(Method::POST, "/api/v1/uploads/multipart/part")
+      .json(&json!({ "id": self.id, "part_number": part_number }));
+
+    let response = client.send(request).await;
+
+    let part_url: PartUrl = handle_response(response).await?;
+
+    let file_size = std::fs::metadata(path)?.len();
+    let offset = (part_number - 1) * self.part_size;
+    let length = self.part_length(part_number, file_size);
+
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+
+    let mut bytes_sent = 0;
+    let stream = FramedRead::new(file.take(length), BytesCodec::new()).inspect(move |chunk| {
+      if let Ok(chunk) = chunk {
+        bytes_sent += chunk.len() as u64;
+        on_progress(bytes_sent);
+      }
+    });
 
-    let response = client()
-      .post(&endpoint)
-      .header("content-type", "application/json")
-      .header("authorization", format!("Bearer {}", config.api_token))
-      .json(&finalize_request)
+    let response = client
+      .put(&part_url.url)
+      .header("content-length", length)
+      .body(Body::wrap_stream(stream))
       .send()
       .await;
 
-    handle_response(response).await
+    let response = handle_put_response(response).await?;
+    let etag = part_etag(&response)?;
+
+    Ok(CompletedPart { part_number, etag })
+  }
+
+  pub async fn complete(&self, client: &ApiClient) -> Result<(), ApiError> {
+    let mut parts = self.parts.clone();
+    parts.sort_by_key(|part| part.part_number);
+
+    let request = client
+      .request(Method::POST, "/api/v1/uploads/multipart/complete")
+      .json(&json!({ "id": self.id, "parts": parts }));
+
+    let response = client.send(request).await;
+
+    handle_response::<serde_json::Value>(response)
+      .await
+      .map(|_| ())
+  }
+}
+
+/// The ETag storage answers an uploaded part with, which completing the
+/// upload has to list.
+fn part_etag(response: &Response) -> Result<String, ApiError> {
+  match response.headers().get("etag").map(|etag| etag.to_str()) {
+    Some(Ok(etag)) if !etag.is_empty() => Ok(etag.to_owned()),
+    _ => Err(ApiError::Protocol("part response missing ETag".to_owned())),
+  }
+}
+
+const MAX_REASON_LENGTH: usize = 200;
+
+/// Turns an unsuccessful response into an [`ApiError`], keeping the message
+/// from its body.
+async fn error_from_response(response: Response) -> ApiError {
+  let status = response.status();
+  let body = response.text().await.unwrap_or_default();
+  let message = error_message(&body);
+
+  match status {
+    StatusCode::UNAUTHORIZED => ApiError::Unauthorized(message),
+    StatusCode::FORBIDDEN => ApiError::Forbidden(message),
+    StatusCode::NOT_FOUND => ApiError::NotFound(message),
+    StatusCode::CONFLICT => ApiError::Conflict(message),
+    StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge(message),
+    StatusCode::UNPROCESSABLE_ENTITY => ApiError::Unprocessable(message),
+    StatusCode::TOO_MANY_REQUESTS => ApiError::RateLimited(message),
+    status if status.is_server_error() => ApiError::ServerError(status.as_u16(), message),
+    status => ApiError::HttpError(status.as_u16(), message),
+  }
+}
+
+/// Picks the error message out of a response body.
+///
+/// Understands `{"error": ...}`, `{"message": ...}` and `{"errors": [...]}`
+/// bodies and falls back to the body as text, e.g. for S3's XML errors.
+fn error_message(body: &str) -> Option<String> {
+  let message = match serde_json::from_str::<serde_json::Value>(body) {
+    Ok(json) => {
+      let field = json.get("error").or_else(|| json.get("message"));
+
+      match (field, json.get("errors")) {
+        (Some(serde_json::Value::String(message)), _) => message.clone(),
+        (_, Some(serde_json::Value::Array(errors))) => errors
+          .iter()
+          .map(|error| match error {
+            serde_json::Value::String(error) => error.clone(),
+            error => error.to_string(),
+          })
+          .collect::<Vec<_>>()
+          .join(", "),
+        _ => body.to_owned(),
+      }
+    }
+    Err(_) => body.to_owned(),
+  };
+
+  let message = message.trim();
+
+  if message.is_empty() {
+    None
+  } else {
+    Some(message.chars().take(MAX_REASON_LENGTH).collect())
+  }
+}
+
+async fn handle_put_response(
+  response: Result<Response, reqwest::Error>,
+) -> Result<Response, ApiError> {
+  let response = response?;
+
+  if response.status().is_success() {
+    Ok(response)
+  } else {
+    Err(error_from_response(response).await)
   }
 }
 
 async fn handle_response<T: DeserializeOwned>(
   response: Result<Response, reqwest::Error>,
 ) -> Result<T, ApiError> {
-  match response {
-    Ok(response) => match response.status() {
-      StatusCode::FORBIDDEN => {
-        return Err(ApiError::ApiKeyError);
-      }
-      status => {
-        dbg!(&status);
-
-        if status.is_success() {
-          match response.json().await {
-            Ok(json) => Ok(json),
-            Err(_) => Err(ApiError::JsonError),
-          }
-        } else {
-          Err(ApiError::NotFound)
-        }
-      }
-    },
-    Err(_) => Err(ApiError::ServerUnavailable),
+  let response = response?;
+
+  if response.status().is_success() {
+    response.json().await.map_err(|_| ApiError::JsonError)
+  } else {
+    Err(error_from_response(response).await)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn response(builder: hyper::http::response::Builder, body: &'static str) -> Response {
+    Response::from(builder.body(body).unwrap())
+  }
+
+  #[tokio::test]
+  async fn maps_statuses_to_errors() {
+    let body = r#"{"error": "Nope"}"#;
+    let nope = || Some("Nope".to_owned());
+    let cases = vec![
+      (401, ApiError::Unauthorized(nope())),
+      (403, ApiError::Forbidden(nope())),
+      (404, ApiError::NotFound(nope())),
+      (409, ApiError::Conflict(nope())),
+      (413, ApiError::PayloadTooLarge(nope())),
+      (422, ApiError::Unprocessable(nope())),
+      (429, ApiError::RateLimited(nope())),
+      (500, ApiError::ServerError(500, nope())),
+      (503, ApiError::ServerError(503, nope())),
+      (400, ApiError::HttpError(400, nope())),
+      (418, ApiError::HttpError(418, nope())),
+    ];
+
+    for (status, expected) in cases {
+      let error = error_from_response(response(hyper::Response::builder().status(status), body));
+      assert_eq!(error.await, expected, "status {}", status);
+    }
+
+    let empty = response(hyper::Response::builder().status(404), "");
+    assert_eq!(error_from_response(empty).await, ApiError::NotFound(None));
+  }
+
+  #[test]
+  fn reads_error_messages() {
+    let long = "x".repeat(MAX_REASON_LENGTH * 2);
+    let cases: Vec<(&str, Option<String>)> = vec![
+      (
+        r#"{"error": "Invalid tags"}"#,
+        Some("Invalid tags".to_owned()),
+      ),
+      (r#"{"message": "Slow down"}"#, Some("Slow down".to_owned())),
+      (
+        r#"{"errors": ["Tags are missing", {"field": "source"}]}"#,
+        Some(r#"Tags are missing, {"field":"source"}"#.to_owned()),
+      ),
+      // `error` wins over `message`.
+      (
+        r#"{"error": "First", "message": "Second"}"#,
+        Some("First".to_owned()),
+      ),
+      (
+        "<Error><Code>AccessDenied</Code></Error>\n",
+        Some("<Error><Code>AccessDenied</Code></Error>".to_owned()),
+      ),
+      ("  Bad gateway  ", Some("Bad gateway".to_owned())),
+      // JSON without a known field is kept as text.
+      (r#"{"status": 500}"#, Some(r#"{"status": 500}"#.to_owned())),
+      ("", None),
+      ("   ", None),
+      (r#"{"error": ""}"#, None),
+      (&long, Some("x".repeat(MAX_REASON_LENGTH))),
+    ];
+
+    for (body, expected) in cases {
+      assert_eq!(error_message(body), expected, "body {:?}", body);
+    }
+  }
+
+  #[test]
+  fn only_server_side_errors_are_transient() {
+    let transient = vec![
+      ApiError::RateLimited(None),
+      ApiError::ServerError(500, None),
+      ApiError::ServerError(503, None),
+      ApiError::Timeout,
+      ApiError::ServerUnavailable,
+    ];
+    let permanent = vec![
+      ApiError::Unauthorized(None),
+      ApiError::Forbidden(None),
+      ApiError::NotFound(None),
+      ApiError::Conflict(None),
+      ApiError::PayloadTooLarge(None),
+      ApiError::Unprocessable(None),
+      ApiError::HttpError(400, None),
+      ApiError::JsonError,
+      ApiError::FileError("gone".to_owned()),
+      ApiError::Protocol("odd".to_owned()),
+    ];
+
+    for error in transient {
+      assert!(error.is_transient(), "{:?}", error);
+    }
+
+    for error in permanent {
+      assert!(!error.is_transient(), "{:?}", error);
+    }
+  }
+
+  #[test]
+  fn reads_part_etags() {
+    let with_etag = response(hyper::Response::builder().header("etag", "\"abc\""), "");
+    assert_eq!(part_etag(&with_etag).unwrap(), "\"abc\"");
+
+    let without_etag = response(hyper::Response::builder(), "");
+    assert_eq!(
+      part_etag(&without_etag),
+      Err(ApiError::Protocol("part response missing ETag".to_owned()))
+    );
+
+    let empty_etag = response(hyper::Response::builder().header("etag", ""), "");
+    assert!(part_etag(&empty_etag).is_err());
   }
 }
@@ -0,0 +1,370 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use futures::channel::mpsc;
+use futures::{future, StreamExt};
+
+use crate::api::{self, ApiClient, Config};
+use crate::config;
+use crate::hash_cache::HashCache;
+use crate::rules::RuleSet;
+use crate::scan_path;
+use crate::tags::{self, Tag};
+use crate::upload::FileUpload;
+use crate::widgets::file::{self, File, FileMessage, FileState};
+
+const USAGE: &str = "Usage: mx-gui upload [OPTIONS] <PATH>...
+
+Hashes every video found under PATH, skips files already on the archive and
+uploads the rest without opening a window.
+
+Tags are taken from the default tags in the settings file, --tags and, for
+each video, a sidecar file with the same name and a .tags extension (e.g.
+clip.tags next to clip.mp4). Rules in the settings file add tags, a source
+and a description based on each video's path.
+
+Hashes are remembered between runs, so unchanged files are not read again.
+
+Options:
+  --profile <NAME>  Saved profile to use (defaults to the active profile)
+  --host <URL>      Server to upload to (overrides the profile's host)
+  --api-key <KEY>   API key to use (defaults to $MX_API_KEY or the profile's key)
+  --tags <TAGS>     Tags applied to every uploaded file
+  --rehash          Hash every file again instead of using remembered hashes
+  -h, --help        Print this help";
+
+#[derive(Debug, Default)]
+struct Options {
+  profile: Option<String>,
+  host: Option<String>,
+  api_key: Option<String>,
+  tags: Vec<Tag>,
+  rules: RuleSet,
+  rehash: bool,
+  paths: Vec<PathBuf>,
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+  let mut options = Options::default();
+
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--profile" => {
+        options.profile = Some(args.next().ok_or("--profile requires a value")?);
+      }
+      "--host" => {
+        options.host = Some(args.next().ok_or("--host requires a value")?);
+      }
+      "--api-key" => {
+        options.api_key = Some(args.next().ok_or("--api-key requires a value")?);
+      }
+      "--tags" => {
+        let value = args.next().ok_or("--tags requires a value")?;
+        options.tags = tags::parse_list(&value).map_err(|err| err.to_string())?;
+      }
+      "--rehash" => options.rehash = true,
+      flag if flag.starts_with("--") => return Err(format!("Unknown option: {}", flag)),
+      path => options.paths.push(PathBuf::from(path)),
+    }
+  }
+
+  if options.paths.is_empty() {
+    return Err("No paths given".to_owned());
+  }
+
+  Ok(options)
+}
+
+pub fn run(args: Vec<String>) -> i32 {
+  let mut args = args.into_iter();
+
+  match args.next().as_deref() {
+    Some("upload") => {}
+    Some("help") | Some("-h") | Some("--help") => {
+      println!("{}", USAGE);
+      return 0;
+    }
+    Some(command) => {
+      eprintln!("Unknown command: {}\n\n{}", command, USAGE);
+      return 2;
+    }
+    None => {
+      eprintln!("{}", USAGE);
+      return 2;
+    }
+  }
+
+  let args: Vec<String> = args.collect();
+
+  if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+    println!("{}", USAGE);
+    return 0;
+  }
+
+  let options = match parse_options(args.into_iter()) {
+    Ok(options) => options,
+    Err(err) => {
+      eprintln!("{}\n\n{}", err, USAGE);
+      return 2;
+    }
+  };
+
+  let settings = match config::read_settings() {
+    Ok(settings) => settings,
+    Err(err) => {
+      eprintln!("Could not read settings, using the defaults: {}", err);
+      config::Settings::default()
+    }
+  };
+  let profiles = &settings.profiles;
+  let profile = match &options.profile {
+    Some(name) => match profiles.get(name) {
+      Some(profile) => Some(profile),
+      None => {
+        eprintln!("No saved profile named {}", name);
+        return 2;
+      }
+    },
+    None => profiles.active_profile(),
+  };
+
+  let host = options
+    .host
+    .clone()
+    .or_else(|| profile.map(|profile| profile.host.clone()))
+    .unwrap_or_else(|| config::DEFAULT_HOST.to_owned());
+
+  let api_key = options
+    .api_key
+    .clone()
+    .or_else(|| std::env::var("MX_API_KEY").ok())
+    .or_else(|| profile.map(|profile| profile.api_key.clone()));
+
+  match api_key {
+    Some(api_key) => {
+      let mut tags = match tags::parse_list(&settings.default_tags) {
+        Ok(tags) => tags,
+        Err(err) => {
+          eprintln!("Invalid default tags in the settings: {}", err);
+          return 2;
+        }
+      };
+
+      for tag in options.tags.iter().cloned() {
+        tags::add(&mut tags, tag);
+      }
+
+      let rules = match RuleSet::compile(&settings.rules) {
+        (rules, errors) if errors.is_empty() => rules,
+        (_, errors) => {
+          for err in errors {
+            eprintln!("Invalid rule in the settings: {}", err);
+          }
+          return 2;
+        }
+      };
+
+      let options = Options {
+        tags,
+        rules,
+        ..options
+      };
+
+      let config = Config::new(host, api_key.trim().to_owned());
+
+      match ApiClient::new(config, &settings.network) {
+        Ok(client) => upload(
+          client.with_rate_limit(settings.requests_per_second),
+          options,
+        ),
+        Err(err) => {
+          eprintln!("Invalid network settings: {}", err);
+          2
+        }
+      }
+    }
+    None => {
+      eprintln!("No API key given. Pass --api-key or set MX_API_KEY.");
+      2
+    }
+  }
+}
+
+/// Tags from the command line combined with the file's sidecar, if any.
+fn tags_for(path: &Path, tags: &[Tag]) -> Vec<Tag> {
+  let sidecar_path = path.with_extension("tags");
+  let sidecar = std::fs::read_to_string(&sidecar_path).unwrap_or_default();
+  let mut tags = tags.to_vec();
+
+  for raw in sidecar.split_whitespace() {
+    match Tag::parse(raw) {
+      Ok(tag) => tags::add(&mut tags, tag),
+      Err(err) => eprintln!("Ignoring tag in {}: {}", sidecar_path.display(), err),
+    }
+  }
+
+  tags
+}
+
+fn print_progress(position: &str, file: &File) {
+  if let Some(progress) = file.progress {
+    print!(
+      "\r{} {} {:.0}% ({} / {}, {}/s)   ",
+      position,
+      file.file_name(),
+      progress.percent(),
+      file::format_bytes(progress.bytes_sent),
+      file::format_bytes(progress.total_bytes),
+      file::format_bytes(progress.speed() as u64),
+    );
+    let _ = std::io::stdout().flush();
+  }
+}
+
+#[tokio::main]
+async fn upload(client: ApiClient, options: Options) -> i32 {
+  match api::User::get(&client).await {
+    Ok(user) => println!("Logged in as: {}", user.username),
+    Err(err) => {
+      eprintln!("Could not log in: {}", err);
+      return 1;
+    }
+  }
+
+  let mut paths: Vec<PathBuf> = Vec::new();
+  let mut failures = 0;
+
+  for scanned in options.paths.iter().cloned().flat_map(scan_path) {
+    let path = scanned.path;
+
+    match scanned.format {
+      Ok(format) if !format.matches_extension(&path) => {
+        eprintln!("Warning: {} contains {} video", path.display(), format);
+      }
+      Ok(_) => {}
+      Err(reason) => {
+        eprintln!("Skipping {}: {}", path.display(), reason);
+        failures += 1;
+        continue;
+      }
+    }
+
+    if !paths.contains(&path) {
+      paths.push(path);
+    }
+  }
+
+  let mut files = Vec::new();
+  let mut hash_cache = HashCache::load();
+
+  for (id, path) in paths.into_iter().enumerate() {
+    let id = id as u64;
+    let cached_md5 = if options.rehash {
+      None
+    } else {
+      hash_cache.get(&path)
+    };
+
+    if cached_md5.is_none() {
+      println!("Hashing {}", path.display());
+    }
+
+    match File::analyze_file(id, path.clone(), cached_md5, |_| {}).await {
+      Ok(analysis) => {
+        let fingerprint = analysis.fingerprint().cloned();
+        let mut file = File {
+          id,
+          tags: tags_for(&path, &options.tags),
+          path,
+          ..Default::default()
+        };
+
+        options.rules.apply(&mut file);
+
+        file.update(FileMessage::Analyzed(analysis));
+
+        if let (None, Some(fingerprint), Some(md5)) = (cached_md5, fingerprint, file.md5) {
+          hash_cache.insert(&file.path, fingerprint, md5);
+        }
+
+        match file.state {
+          FileState::Invalid => {
+            eprintln!(
+              "Skipping {}: {}",
+              file.path.display(),
+              file.invalid_reason.as_deref().unwrap_or_default()
+            );
+            failures += 1;
+          }
+          _ => files.push(file),
+        }
+      }
+      Err(err) => {
+        eprintln!("Could not read {}: {}", path.display(), err);
+        failures += 1;
+      }
+    }
+  }
+
+  hash_cache.save();
+
+  if files.is_empty() {
+    println!("No videos found.");
+    return if failures > 0 { 1 } else { 0 };
+  }
+
+  let checksums: Vec<String> = files.iter().map(|file| file.get_md5()).collect();
+  let duplicates = match api::Checksums::check(&checksums, &client).await {
+    Ok(response) => response.checksums,
+    Err(err) => {
+      eprintln!("Could not check for duplicates: {}", err);
+      return 1;
+    }
+  };
+
+  let total = files.len();
+
+  for (idx, mut file) in files.into_iter().enumerate() {
+    let position = format!("[{}/{}]", idx + 1, total);
+
+    if duplicates.contains(&file.get_md5()) {
+      println!(
+        "{} {} is already on the archive, skipping",
+        position,
+        file.file_name()
+      );
+      continue;
+    }
+
+    let (sender, receiver) = mpsc::unbounded();
+    let upload = FileUpload::new(&client, &file).run(sender);
+    let progress = receiver.for_each(|event| {
+      file.update(FileMessage::from(event));
+      print_progress(&position, &file);
+      future::ready(())
+    });
+
+    let (result, _) = future::join(upload, progress).await;
+
+    match result {
+      Ok(_) => println!("\r{} {} uploaded{:30}", position, file.file_name(), ""),
+      Err(err) => {
+        println!(
+          "\r{} {} failed: {}{:30}",
+          position,
+          file.file_name(),
+          err,
+          ""
+        );
+        failures += 1;
+      }
+    }
+  }
+
+  if failures > 0 {
+    eprintln!("{} file(s) failed", failures);
+    1
+  } else {
+    0
+  }
+}
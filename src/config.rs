@@ -1,13 +1,336 @@
-use tokio::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
-#[tokio::main]
-pub async fn read_api_key() -> Result<String, anyhow::Error> {
-  let key = fs::read_to_string("spin-archive.key").await?;
-  Ok(key)
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::api;
+use crate::credentials;
+use crate::hashing;
+use crate::rules::Rule;
+use crate::scheduler;
+use crate::tags::{self, Tag};
+
+pub const DEFAULT_HOST: &str = "https://spin-archive.org";
+
+/// Version of the settings layout written by this build.
+///
+/// Bump it whenever the layout changes and teach [`migrate`] how to bring
+/// older files up to date.
+pub const SETTINGS_VERSION: u64 = 2;
+
+pub const SETTINGS_DIR: &str = "mx";
+const SETTINGS_FILE: &str = "settings.json";
+
+// Files older versions kept in the working directory.
+const LEGACY_PROFILES_PATH: &str = "mx-profiles.json";
+const LEGACY_API_KEY_PATH: &str = "spin-archive.key";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+  pub name: String,
+  pub host: String,
+  /// Kept in the credential store; only read here to migrate files from
+  /// before version 2.
+  #[serde(default, skip_serializing)]
+  pub api_key: String,
+}
+
+impl Profile {
+  pub fn config(&self) -> api::Config {
+    api::Config::new(self.host.clone(), self.api_key.clone())
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Profiles {
+  pub active: Option<String>,
+  pub profiles: Vec<Profile>,
+}
+
+impl Profiles {
+  pub fn get(&self, name: &str) -> Option<&Profile> {
+    self.profiles.iter().find(|profile| profile.name == name)
+  }
+
+  pub fn active_profile(&self) -> Option<&Profile> {
+    self.active.as_ref().and_then(|name| self.get(name))
+  }
+
+  pub fn activate(&mut self, profile: Profile) {
+    self.active = Some(profile.name.clone());
+
+    match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+      Some(existing) => *existing = profile,
+      None => self.profiles.push(profile),
+    }
+  }
+
+  pub fn remove(&mut self, name: &str) {
+    self.profiles.retain(|profile| profile.name != name);
+
+    if self.active.as_deref() == Some(name) {
+      self.active = None;
+    }
+  }
+}
+
+/// Colour scheme of the window.
+///
+/// Only the dark palette exists so far; the choice is stored so it survives
+/// once more are added.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+  #[default]
+  Dark,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct WindowGeometry {
+  pub width: u32,
+  pub height: u32,
+}
+
+impl Default for WindowGeometry {
+  fn default() -> Self {
+    Self {
+      width: 1024,
+      height: 768,
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TagPreset {
+  pub name: String,
+  #[serde(deserialize_with = "tags::deserialize_stored")]
+  pub tags: Vec<Tag>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+  pub version: u64,
+  #[serde(flatten)]
+  pub profiles: Profiles,
+  pub upload_concurrency: usize,
+  pub hash_workers: usize,
+  /// Budget for API requests across all uploads; zero disables the limit.
+  pub requests_per_second: f64,
+  pub network: api::ClientOptions,
+  pub default_tags: String,
+  pub tag_presets: Vec<TagPreset>,
+  /// Evaluated in order whenever files are added.
+  pub rules: Vec<Rule>,
+  pub theme: Theme,
+  pub window: WindowGeometry,
 }
 
-#[tokio::main]
-pub async fn write_api_key(key: &str) -> Result<(), anyhow::Error> {
-  fs::write("spin-archive.key", &key).await?;
+impl Default for Settings {
+  fn default() -> Self {
+    Self {
+      version: SETTINGS_VERSION,
+      profiles: Profiles::default(),
+      upload_concurrency: scheduler::DEFAULT_CONCURRENCY,
+      hash_workers: hashing::DEFAULT_WORKERS,
+      requests_per_second: api::DEFAULT_REQUESTS_PER_SECOND,
+      network: api::ClientOptions::default(),
+      default_tags: String::new(),
+      tag_presets: Vec::new(),
+      rules: Vec::new(),
+      theme: Theme::default(),
+      window: WindowGeometry::default(),
+    }
+  }
+}
+
+/// Where the settings live, e.g. `~/.config/mx/settings.json` on Linux.
+///
+/// Falls back to the working directory on platforms without a config
+/// directory.
+pub fn settings_path() -> PathBuf {
+  dirs::config_dir()
+    .map(|dir| dir.join(SETTINGS_DIR))
+    .unwrap_or_default()
+    .join(SETTINGS_FILE)
+}
+
+fn version_of(value: &Value) -> u64 {
+  value.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+fn migrate(mut value: Value) -> Result<Settings, anyhow::Error> {
+  let version = version_of(&value);
+
+  if version > SETTINGS_VERSION {
+    anyhow::bail!(
+      "settings were written by a newer version of mx (version {})",
+      version
+    );
+  }
+
+  // Version 0 is the old mx-profiles.json, which only held the profiles and
+  // reads as-is; every other field falls back to its default. Versions 0 and
+  // 1 kept API keys in plain text, which `read_settings` moves to the
+  // credential store.
+  value["version"] = SETTINGS_VERSION.into();
+
+  Ok(serde_json::from_value(value)?)
+}
+
+/// Reads the settings file, migrating the files older versions left in the
+/// working directory if there is none yet.
+///
+/// Only a first start, with no settings anywhere, gives the defaults.
+/// Settings that exist but cannot be read are an error, so they are not
+/// replaced with the defaults by mistake.
+pub fn read_settings() -> Result<Settings, anyhow::Error> {
+  let store = credentials::default_store();
+
+  let (mut settings, legacy) = match std::fs::read_to_string(settings_path()) {
+    Ok(contents) => (migrate(serde_json::from_str(&contents)?)?, false),
+    Err(err) if err.kind() == ErrorKind::NotFound => match read_legacy_settings()? {
+      Some(settings) => (settings, true),
+      None => return Ok(Settings::default()),
+    },
+    Err(err) => return Err(err.into()),
+  };
+
+  let mut plain_text_keys = false;
+  let mut keys_stored = true;
+
+  // A locked or missing keyring only costs the affected profiles their key,
+  // not the rest of the settings.
+  for profile in settings.profiles.profiles.iter_mut() {
+    if profile.api_key.is_empty() {
+      match store.get(&profile.name) {
+        Ok(api_key) => profile.api_key = api_key.unwrap_or_default(),
+        Err(err) => eprintln!("Could not read the API key of {}: {}", profile.name, err),
+      }
+    } else {
+      plain_text_keys = true;
+
+      if let Err(err) = store.set(&profile.name, &profile.api_key) {
+        eprintln!("Could not store the API key of {}: {}", profile.name, err);
+        keys_stored = false;
+      }
+    }
+  }
+
+  // Writing the settings drops plain text keys, so the old files stay until
+  // every key made it into the credential store.
+  if (legacy || plain_text_keys) && keys_stored {
+    write_settings(&settings)?;
+
+    // The keys are safe in the credential store now.
+    let _ = std::fs::remove_file(LEGACY_PROFILES_PATH);
+    let _ = std::fs::remove_file(LEGACY_API_KEY_PATH);
+  }
+
+  Ok(settings)
+}
+
+pub fn write_api_key(profile: &Profile) -> Result<(), anyhow::Error> {
+  credentials::default_store().set(&profile.name, &profile.api_key)
+}
+
+pub fn delete_api_key(name: &str) -> Result<(), anyhow::Error> {
+  credentials::default_store().delete(name)
+}
+
+fn read_legacy_settings() -> Result<Option<Settings>, anyhow::Error> {
+  match std::fs::read_to_string(LEGACY_PROFILES_PATH) {
+    Ok(contents) => return Ok(Some(migrate(serde_json::from_str(&contents)?)?)),
+    Err(err) if err.kind() == ErrorKind::NotFound => {}
+    Err(err) => return Err(err.into()),
+  }
+
+  // The oldest versions only stored a bare API key for spin-archive.org.
+  let api_key = match std::fs::read_to_string(LEGACY_API_KEY_PATH) {
+    Ok(api_key) => api_key,
+    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+    Err(err) => return Err(err.into()),
+  };
+  let mut settings = Settings::default();
+
+  settings.profiles.activate(Profile {
+    name: "spin-archive.org".to_owned(),
+    host: DEFAULT_HOST.to_owned(),
+    api_key: api_key.trim().to_owned(),
+  });
+
+  Ok(Some(settings))
+}
+
+pub fn write_settings(settings: &Settings) -> Result<(), anyhow::Error> {
+  write_settings_to(&settings_path(), settings)
+}
+
+/// Writes `settings` to `path` atomically, refusing to replace settings a
+/// newer version of mx wrote.
+fn write_settings_to(path: &Path, settings: &Settings) -> Result<(), anyhow::Error> {
+  if let Ok(contents) = std::fs::read_to_string(path) {
+    if let Ok(existing) = serde_json::from_str::<Value>(&contents) {
+      let version = version_of(&existing);
+
+      if version > SETTINGS_VERSION {
+        anyhow::bail!(
+          "not replacing settings written by a newer version of mx (version {})",
+          version
+        );
+      }
+    }
+  }
+
+  let contents = serde_json::to_string_pretty(settings)?;
+  write_atomic(path, contents.as_bytes())?;
   Ok(())
 }
+
+/// Replaces the file at `path` with `contents`, creating its directory if
+/// needed. The contents go to a temporary file first, so a crash mid-write
+/// never leaves a truncated file behind.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+  let mut temp_path = path.as_os_str().to_owned();
+  temp_path.push(".tmp");
+
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+
+  std::fs::write(&temp_path, contents)?;
+  std::fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn writes_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+
+    let settings = Settings {
+      default_tags: "spin".to_owned(),
+      ..Settings::default()
+    };
+    write_settings_to(&path, &settings).unwrap();
+
+    let value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(migrate(value).unwrap().default_tags, "spin");
+  }
+
+  #[test]
+  fn never_replaces_newer_settings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SETTINGS_FILE);
+    let newer = format!("{{\"version\": {}}}", SETTINGS_VERSION + 1);
+    std::fs::write(&path, &newer).unwrap();
+
+    assert!(migrate(serde_json::from_str(&newer).unwrap()).is_err());
+    assert!(write_settings_to(&path, &Settings::default()).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), newer);
+  }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use secret_service::blocking::SecretService;
+use secret_service::EncryptionType;
+
+const SERVICE: &str = "mx";
+
+const KEY_FILE: &str = "credentials.key";
+const SECRETS_FILE: &str = "credentials.enc";
+
+pub trait CredentialStore {
+  fn get(&self, account: &str) -> Result<Option<String>, anyhow::Error>;
+  fn set(&self, account: &str, secret: &str) -> Result<(), anyhow::Error>;
+  fn delete(&self, account: &str) -> Result<(), anyhow::Error>;
+}
+
+/// Picks the best store available on this machine: the Secret Service if a
+/// keyring is running, an encrypted file in the config directory otherwise.
+///
+/// Without either, keys are only kept for as long as mx runs.
+pub fn default_store() -> Box<dyn CredentialStore> {
+  if SecretServiceStore::is_available() {
+    return Box::new(SecretServiceStore);
+  }
+
+  match dirs::config_dir() {
+    Some(dir) => Box::new(EncryptedFileStore::new(
+      dir.join(crate::config::SETTINGS_DIR),
+    )),
+    None => Box::new(MemoryStore::default()),
+  }
+}
+
+pub struct SecretServiceStore;
+
+impl SecretServiceStore {
+  fn connect() -> Result<SecretService<'static>, anyhow::Error> {
+    Ok(SecretService::connect(EncryptionType::Dh)?)
+  }
+
+  pub fn is_available() -> bool {
+    match Self::connect() {
+      Ok(service) => service.get_default_collection().is_ok(),
+      Err(_) => false,
+    }
+  }
+
+  fn attributes(account: &str) -> HashMap<&str, &str> {
+    let mut attributes = HashMap::new();
+    attributes.insert("service", SERVICE);
+    attributes.insert("account", account);
+    attributes
+  }
+}
+
+impl CredentialStore for SecretServiceStore {
+  fn get(&self, account: &str) -> Result<Option<String>, anyhow::Error> {
+    let service = Self::connect()?;
+    let items = service.search_items(Self::attributes(account))?;
+
+    let item = match items.unlocked.first() {
+      Some(item) => item,
+      None => match items.locked.first() {
+        Some(item) => {
+          item.unlock()?;
+          item
+        }
+        None => return Ok(None),
+      },
+    };
+
+    Ok(Some(String::from_utf8(item.get_secret()?)?))
+  }
+
+  fn set(&self, account: &str, secret: &str) -> Result<(), anyhow::Error> {
+    let service = Self::connect()?;
+    let collection = service.get_default_collection()?;
+
+    collection.ensure_unlocked()?;
+    collection.create_item(
+      &format!("mx API key ({})", account),
+      Self::attributes(account),
+      secret.as_bytes(),
+      true,
+      "text/plain",
+    )?;
+
+    Ok(())
+  }
+
+  fn delete(&self, account: &str) -> Result<(), anyhow::Error> {
+    let service = Self::connect()?;
+    let items = service.search_items(Self::attributes(account))?;
+
+    for item in items.unlocked.iter().chain(items.locked.iter()) {
+      item.delete()?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Keys encrypted with AES-256-GCM in the config directory.
+///
+/// The encryption key lives next to the secrets, readable only by the
+/// current user, so this guards against the keys leaking through backups or
+/// copied settings rather than against someone with access to the account.
+pub struct EncryptedFileStore {
+  dir: PathBuf,
+}
+
+impl EncryptedFileStore {
+  pub fn new(dir: PathBuf) -> Self {
+    Self { dir }
+  }
+
+  /// The encryption key, generated on first use.
+  ///
+  /// A new key is only made while there are no secrets yet; replacing the
+  /// key of existing secrets would make them unreadable for good.
+  fn key(&self) -> Result<Key<Aes256Gcm>, anyhow::Error> {
+    let path = self.dir.join(KEY_FILE);
+
+    match std::fs::read(&path) {
+      Ok(bytes) if bytes.len() == 32 => Ok(*Key::<Aes256Gcm>::from_slice(&bytes)),
+      Ok(_) => anyhow::bail!("{} is corrupt", path.display()),
+      Err(err) if err.kind() == ErrorKind::NotFound && !self.dir.join(SECRETS_FILE).exists() => {
+        let key = Aes256Gcm::generate_key(OsRng);
+        write_private(&path, &key)?;
+        Ok(key)
+      }
+      Err(err) => Err(anyhow::anyhow!(
+        "could not read {}: {}",
+        path.display(),
+        err
+      )),
+    }
+  }
+
+  fn read(&self) -> Result<HashMap<String, String>, anyhow::Error> {
+    let contents = match std::fs::read(self.dir.join(SECRETS_FILE)) {
+      Ok(contents) => contents,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+      Err(err) => return Err(err.into()),
+    };
+
+    if contents.len() < 12 {
+      anyhow::bail!("credentials file is corrupt");
+    }
+
+    let (nonce, ciphertext) = contents.split_at(12);
+    let cipher = Aes256Gcm::new(&self.key()?);
+    let plaintext = cipher
+      .decrypt(Nonce::from_slice(nonce), ciphertext)
+      .map_err(|_| anyhow::anyhow!("could not decrypt credentials"))?;
+
+    Ok(serde_json::from_slice(&plaintext)?)
+  }
+
+  fn write(&self, secrets: &HashMap<String, String>) -> Result<(), anyhow::Error> {
+    let cipher = Aes256Gcm::new(&self.key()?);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+      .encrypt(&nonce, serde_json::to_vec(secrets)?.as_slice())
+      .map_err(|_| anyhow::anyhow!("could not encrypt credentials"))?;
+
+    let mut contents = nonce.to_vec();
+    contents.extend(ciphertext);
+
+    write_private(&self.dir.join(SECRETS_FILE), &contents)
+  }
+}
+
+impl CredentialStore for EncryptedFileStore {
+  fn get(&self, account: &str) -> Result<Option<String>, anyhow::Error> {
+    Ok(self.read()?.remove(account))
+  }
+
+  fn set(&self, account: &str, secret: &str) -> Result<(), anyhow::Error> {
+    let mut secrets = self.read()?;
+    secrets.insert(account.to_owned(), secret.to_owned());
+    self.write(&secrets)
+  }
+
+  fn delete(&self, account: &str) -> Result<(), anyhow::Error> {
+    let mut secrets = self.read()?;
+
+    if secrets.remove(account).is_some() {
+      self.write(&secrets)?;
+    }
+
+    Ok(())
+  }
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+  secrets: Mutex<HashMap<String, String>>,
+}
+
+impl CredentialStore for MemoryStore {
+  fn get(&self, account: &str) -> Result<Option<String>, anyhow::Error> {
+    Ok(self.secrets.lock().unwrap().get(account).cloned())
+  }
+
+  fn set(&self, account: &str, secret: &str) -> Result<(), anyhow::Error> {
+    self
+      .secrets
+      .lock()
+      .unwrap()
+      .insert(account.to_owned(), secret.to_owned());
+    Ok(())
+  }
+
+  fn delete(&self, account: &str) -> Result<(), anyhow::Error> {
+    self.secrets.lock().unwrap().remove(account);
+    Ok(())
+  }
+}
+
+/// Writes `contents` to `path`, readable and writable by the current user
+/// only.
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), anyhow::Error> {
+  use std::io::Write;
+
+  if let Some(dir) = path.parent() {
+    std::fs::create_dir_all(dir)?;
+  }
+
+  let mut options = std::fs::OpenOptions::new();
+  options.write(true).create(true).truncate(true);
+
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+  }
+
+  options.open(path)?.write_all(contents)?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn round_trip(store: &dyn CredentialStore) {
+    assert_eq!(store.get("local").unwrap(), None);
+
+    store.set("local", "secret").unwrap();
+    store.set("remote", "other").unwrap();
+    assert_eq!(store.get("local").unwrap().as_deref(), Some("secret"));
+
+    store.set("local", "changed").unwrap();
+    assert_eq!(store.get("local").unwrap().as_deref(), Some("changed"));
+
+    store.delete("local").unwrap();
+    assert_eq!(store.get("local").unwrap(), None);
+    assert_eq!(store.get("remote").unwrap().as_deref(), Some("other"));
+  }
+
+  #[test]
+  fn memory_store_round_trips() {
+    round_trip(&MemoryStore::default());
+  }
+
+  #[test]
+  fn encrypted_file_store_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    round_trip(&EncryptedFileStore::new(dir.path().join("mx")));
+
+    // A new store over the same files reads what the first one wrote.
+    let store = EncryptedFileStore::new(dir.path().join("mx"));
+    assert_eq!(store.get("remote").unwrap().as_deref(), Some("other"));
+
+    let contents = std::fs::read(dir.path().join("mx").join(SECRETS_FILE)).unwrap();
+    assert!(!contents.windows(5).any(|window| window == b"other"));
+  }
+
+  #[test]
+  fn missing_key_is_not_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = EncryptedFileStore::new(dir.path().to_owned());
+    store.set("local", "secret").unwrap();
+
+    let secrets = std::fs::read(dir.path().join(SECRETS_FILE)).unwrap();
+    std::fs::remove_file(dir.path().join(KEY_FILE)).unwrap();
+
+    assert!(store.get("local").is_err());
+    assert!(store.set("local", "other").is_err());
+    assert!(!dir.path().join(KEY_FILE).exists());
+    assert_eq!(
+      std::fs::read(dir.path().join(SECRETS_FILE)).unwrap(),
+      secrets
+    );
+  }
+
+  #[test]
+  fn corrupt_key_is_not_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = EncryptedFileStore::new(dir.path().to_owned());
+    store.set("local", "secret").unwrap();
+
+    std::fs::write(dir.path().join(KEY_FILE), b"short").unwrap();
+
+    assert!(store.get("local").is_err());
+    assert_eq!(std::fs::read(dir.path().join(KEY_FILE)).unwrap(), b"short");
+  }
+}
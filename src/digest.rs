@@ -0,0 +1,27 @@
+pub fn parse(hex: &str) -> Option<md5::Digest> {
+  if hex.len() != 32 {
+    return None;
+  }
+
+  let mut bytes = [0u8; 16];
+
+  for (idx, byte) in bytes.iter_mut().enumerate() {
+    *byte = u8::from_str_radix(hex.get(idx * 2..idx * 2 + 2)?, 16).ok()?;
+  }
+
+  Some(md5::Digest(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips_hex() {
+    let digest = md5::compute(b"hello");
+
+    assert_eq!(parse(&format!("{:x}", digest)), Some(digest));
+    assert_eq!(parse("5d41402abc4b2a76b9719d911017c59"), None);
+    assert_eq!(parse("zz41402abc4b2a76b9719d911017c592"), None);
+  }
+}
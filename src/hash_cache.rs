@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::digest;
+
+const CACHE_FILE: &str = "hash-cache.json";
+
+/// Number of new hashes after which the cache is written out even though
+/// hashing is still going on, so a crash loses little work.
+const SAVE_EVERY: usize = 25;
+
+/// What a file looked like when it was hashed. Any difference means it
+/// changed since and has to be hashed again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+  size: u64,
+  modified_secs: u64,
+  modified_nanos: u32,
+  /// Inode number on Unix, to notice a file replaced by another one.
+  #[serde(default)]
+  inode: Option<u64>,
+}
+
+impl Fingerprint {
+  pub fn of(path: &Path) -> Option<Self> {
+    let meta = fs::metadata(path).ok()?;
+    let modified = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+    Some(Self {
+      size: meta.len(),
+      modified_secs: modified.as_secs(),
+      modified_nanos: modified.subsec_nanos(),
+      inode: inode(&meta),
+    })
+  }
+}
+
+#[cfg(unix)]
+fn inode(meta: &fs::Metadata) -> Option<u64> {
+  use std::os::unix::fs::MetadataExt;
+
+  Some(meta.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &fs::Metadata) -> Option<u64> {
+  None
+}
+
+/// Where and what a file is, which is all a lookup needs. Taken apart from
+/// the cache, as it reads from the disk.
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+  path: PathBuf,
+  fingerprint: Fingerprint,
+}
+
+impl CacheKey {
+  pub fn of(path: &Path) -> Option<Self> {
+    Some(Self {
+      path: fs::canonicalize(path).ok()?,
+      fingerprint: Fingerprint::of(path)?,
+    })
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheEntry {
+  path: PathBuf,
+  #[serde(flatten)]
+  fingerprint: Fingerprint,
+  md5: String,
+}
+
+/// MD5 hashes of files hashed before, so adding the same folder again does
+/// not read every file again.
+///
+/// Entries are keyed by canonical path and only used while the file's size,
+/// modification time and inode are unchanged.
+#[derive(Debug, Default)]
+pub struct HashCache {
+  path: Option<PathBuf>,
+  entries: HashMap<PathBuf, CacheEntry>,
+  unsaved: usize,
+}
+
+pub fn cache_path() -> PathBuf {
+  dirs::cache_dir()
+    .map(|dir| dir.join(config::SETTINGS_DIR))
+    .unwrap_or_default()
+    .join(CACHE_FILE)
+}
+
+impl HashCache {
+  pub fn load() -> Self {
+    Self::load_from(cache_path())
+  }
+
+  fn load_from(path: PathBuf) -> Self {
+    let entries: Vec<CacheEntry> = fs::read_to_string(&path)
+      .ok()
+      .and_then(|contents| serde_json::from_str(&contents).ok())
+      .unwrap_or_default();
+
+    Self {
+      path: Some(path),
+      entries: entries
+        .into_iter()
+        .map(|entry| (entry.path.clone(), entry))
+        .collect(),
+      unsaved: 0,
+    }
+  }
+
+  pub fn get(&self, path: &Path) -> Option<md5::Digest> {
+    self.lookup(&CacheKey::of(path)?)
+  }
+
+  /// Like [`HashCache::get`], without touching the disk.
+  pub fn lookup(&self, key: &CacheKey) -> Option<md5::Digest> {
+    let entry = self.entries.get(&key.path)?;
+
+    if key.fingerprint == entry.fingerprint {
+      digest::parse(&entry.md5)
+    } else {
+      None
+    }
+  }
+
+  /// Remembers the hash of the file at `path`, saving the cache every
+  /// [`SAVE_EVERY`] new hashes.
+  ///
+  /// `fingerprint` has to be taken before the file was read, so a file that
+  /// was still being written to is never stored with an outdated hash.
+  pub fn insert(&mut self, path: &Path, fingerprint: Fingerprint, md5: md5::Digest) {
+    let path = match fs::canonicalize(path) {
+      Ok(path) => path,
+      Err(_) => return,
+    };
+
+    self.entries.insert(
+      path.clone(),
+      CacheEntry {
+        path,
+        fingerprint,
+        md5: format!("{:x}", md5),
+      },
+    );
+
+    self.unsaved += 1;
+
+    if self.unsaved >= SAVE_EVERY {
+      self.save();
+    }
+  }
+
+  pub fn remove(&mut self, path: &Path) {
+    if let Ok(path) = fs::canonicalize(path) {
+      if self.entries.remove(&path).is_some() {
+        self.unsaved += 1;
+      }
+    }
+  }
+
+  pub fn save(&mut self) {
+    if self.unsaved == 0 {
+      return;
+    }
+
+    match self.write() {
+      Ok(()) => self.unsaved = 0,
+      Err(err) => eprintln!("Could not save the hash cache: {}", err),
+    }
+  }
+
+  fn write(&self) -> Result<(), anyhow::Error> {
+    let path = match &self.path {
+      Some(path) => path,
+      None => return Ok(()),
+    };
+    let entries: Vec<&CacheEntry> = self.entries.values().collect();
+    let contents = serde_json::to_string(&entries)?;
+    config::write_atomic(path, contents.as_bytes())?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn remembers_unchanged_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join(CACHE_FILE);
+    let video = dir.path().join("clip.mp4");
+    fs::write(&video, b"hello").unwrap();
+
+    let md5 = md5::compute(b"hello");
+    let mut cache = HashCache::load_from(cache_path.clone());
+    assert_eq!(cache.get(&video), None);
+
+    cache.insert(&video, Fingerprint::of(&video).unwrap(), md5);
+    cache.save();
+
+    let mut cache = HashCache::load_from(cache_path.clone());
+    assert_eq!(cache.get(&video), Some(md5));
+
+    cache.remove(&video);
+    cache.save();
+    assert_eq!(HashCache::load_from(cache_path).get(&video), None);
+  }
+
+  #[test]
+  fn forgets_changed_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let video = dir.path().join("clip.mp4");
+    fs::write(&video, b"hello").unwrap();
+
+    let mut cache = HashCache::load_from(dir.path().join(CACHE_FILE));
+    cache.insert(
+      &video,
+      Fingerprint::of(&video).unwrap(),
+      md5::compute(b"hello"),
+    );
+
+    fs::write(&video, b"hello, world").unwrap();
+    assert_eq!(cache.get(&video), None);
+  }
+
+  #[test]
+  fn stale_fingerprint_is_never_used() {
+    let dir = tempfile::tempdir().unwrap();
+    let video = dir.path().join("clip.mp4");
+    fs::write(&video, b"hel").unwrap();
+
+    // Taken before the file was finished, as when it is still downloading.
+    let before = Fingerprint::of(&video).unwrap();
+    fs::write(&video, b"hello").unwrap();
+
+    let mut cache = HashCache::load_from(dir.path().join(CACHE_FILE));
+    cache.insert(&video, before, md5::compute(b"hel"));
+    assert_eq!(cache.get(&video), None);
+  }
+
+  #[test]
+  fn corrupt_cache_starts_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join(CACHE_FILE);
+    fs::write(&cache_path, b"{ not json").unwrap();
+
+    assert!(HashCache::load_from(cache_path).entries.is_empty());
+  }
+}
@@ -0,0 +1,117 @@
+use std::hash::Hash;
+use std::path::PathBuf;
+
+use futures::channel::mpsc;
+use futures::stream::{self, BoxStream, StreamExt};
+use iced_native::subscription::Recipe;
+
+use crate::message::Message;
+use crate::progress;
+use crate::widgets::file::{File, FileMessage, FileState};
+
+pub const MIN_WORKERS: usize = 1;
+pub const MAX_WORKERS: usize = 8;
+pub const DEFAULT_WORKERS: usize = 2;
+
+/// The files to hash right now: the first `workers` files still waiting for
+/// it, in the order they were added. The rest wait for a worker to free up.
+///
+/// The order does not follow the list's sorting, so re-sorting or adding
+/// files never swaps out a file that is halfway hashed.
+pub fn next_batch(files: &[File], workers: usize) -> Vec<&File> {
+  let mut waiting: Vec<&File> = files
+    .iter()
+    .filter(|file| file.state == FileState::Analyzing)
+    .collect();
+
+  waiting.sort_by_key(|file| file.id);
+  waiting.truncate(workers.clamp(MIN_WORKERS, MAX_WORKERS));
+  waiting
+}
+
+pub struct FileHash {
+  id: u64,
+  path: PathBuf,
+  cached_md5: Option<md5::Digest>,
+}
+
+impl FileHash {
+  pub fn new(file: &File) -> Self {
+    Self {
+      id: file.id,
+      path: file.path.clone(),
+      cached_md5: file.cached_md5,
+    }
+  }
+}
+
+impl<H, I> Recipe<H, I> for FileHash
+where
+  H: std::hash::Hasher,
+{
+  type Output = Message;
+
+  fn hash(&self, state: &mut H) {
+    std::any::TypeId::of::<Self>().hash(state);
+    self.id.hash(state);
+  }
+
+  fn stream(self: Box<Self>, _input: BoxStream<'static, I>) -> BoxStream<'static, Message> {
+    let id = self.id;
+    let (sender, receiver) = mpsc::unbounded();
+
+    let progress = receiver.map(move |bytes| Message::FileMessage(id, FileMessage::Hashed(bytes)));
+
+    let result = stream::once(async move {
+      let on_progress = progress::throttled(move |bytes_hashed| {
+        let _ = sender.unbounded_send(bytes_hashed);
+      });
+      let result = File::analyze_file(id, self.path, self.cached_md5, on_progress).await;
+
+      Message::FileAnalyzed(id, result)
+    });
+
+    Box::pin(stream::select(progress, result))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn file(id: u64, state: FileState) -> File {
+    File {
+      id,
+      state,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn batch_follows_insertion_order_not_sorting() {
+    let files = vec![
+      file(4, FileState::Analyzing),
+      file(0, FileState::Analyzed),
+      file(3, FileState::Analyzing),
+      file(1, FileState::Analyzing),
+      file(2, FileState::Invalid),
+    ];
+
+    let ids =
+      |files: &[File]| -> Vec<u64> { next_batch(files, 2).iter().map(|file| file.id).collect() };
+
+    assert_eq!(ids(&files), vec![1, 3]);
+
+    let mut reversed = files;
+    reversed.reverse();
+    assert_eq!(ids(&reversed), vec![1, 3]);
+  }
+
+  #[test]
+  fn batch_size_is_clamped() {
+    let files: Vec<File> = (0..20).map(|id| file(id, FileState::Analyzing)).collect();
+
+    assert_eq!(next_batch(&files, 0).len(), MIN_WORKERS);
+    assert_eq!(next_batch(&files, 100).len(), MAX_WORKERS);
+  }
+}
@@ -1,43 +1,179 @@
+use std::collections::HashSet;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use iced::{
     button, executor, scrollable, text_input, Align, Application, Button, Color, Column, Command,
-    Container, Element, Length, Row, Scrollable, Settings, Subscription, Text, TextInput,
-    VerticalAlignment,
+    Container, Element, Length, ProgressBar, Row, Scrollable, Settings, Subscription, Text,
+    TextInput, VerticalAlignment,
 };
 use iced_native::input::keyboard::{Event as KeyboardEvent, KeyCode};
 use iced_native::input::ButtonState;
 use iced_native::window::Event as WindowEvent;
 use iced_native::Event;
+use tokio::time::delay_for;
 use walkdir::WalkDir;
 
 mod api;
+mod cli;
 mod config;
+mod credentials;
+mod digest;
+mod hash_cache;
+mod hashing;
 mod message;
+mod metadata;
+mod progress;
+mod rules;
+mod save_timer;
 mod scenes;
+mod scheduler;
+mod session;
+mod sniff;
 mod styles;
+mod tags;
+mod upload;
 mod widgets;
 
-use api::{Config, User};
+use api::{ApiClient, ArchiveApi, User};
+use config::Profile;
+use hash_cache::{CacheKey, HashCache};
+use hashing::FileHash;
 use message::{Filter, Message};
-use scenes::{Scenes, WelcomeScene};
-use widgets::file::{self, File, FileMessage, FileState};
-
-fn is_video(path: &PathBuf) -> bool {
-    let guess = mime_guess::from_path(path);
+use rules::RuleSet;
+use save_timer::SaveTimer;
+use scenes::{Scenes, WelcomeMessage, WelcomeScene};
+use scheduler::UploadScheduler;
+use sniff::VideoFormat;
+use tags::Tag;
+use upload::FileUpload;
+use widgets::file::{self, File, FileMessage, FileState, Sort, SortHeaders, UploadProgress};
+use widgets::presets::{PresetMessage, PresetPanel};
+use widgets::tags::{TagEditor, TagMessage};
+
+/// Set to run the app against an in-memory archive instead of a server.
+#[cfg(feature = "fake-api")]
+const FAKE_API_VAR: &str = "MX_FAKE_API";
+
+/// Files that can be hashed again: not being hashed, uploaded or queued.
+const REHASHABLE_STATES: &[FileState] = &[
+    FileState::Analyzed,
+    FileState::Pending,
+    FileState::Duplicate,
+    FileState::Failed,
+    FileState::Cancelled,
+    FileState::Invalid,
+];
+
+/// A file found under a dropped path. Everything about it that has to be
+/// read from the disk is read while scanning, away from the UI thread.
+#[derive(Debug, Clone)]
+pub struct ScannedFile {
+    pub path: PathBuf,
+    /// The container the contents turned out to be, or why a file named like
+    /// a video was rejected.
+    pub format: Result<VideoFormat, String>,
+    pub size: Option<u64>,
+    pub cache_key: Option<CacheKey>,
+}
 
-    match guess.first() {
-        Some(guess) => guess.to_string().starts_with("video/"),
-        None => false,
-    }
+/// Every video at `path`, walking into it if it is a directory, with the
+/// container its contents turned out to be. Names do not matter, so
+/// extensionless videos are found. Other files are skipped, except those
+/// named like a video, e.g. an `.mp4` that is really an HTML error page,
+/// which are kept as rejected.
+pub fn scan_path(path: PathBuf) -> Vec<ScannedFile> {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter_map(|path| {
+            let format = match sniff::sniff(&path) {
+                Ok(Some(format)) => Ok(format),
+                Ok(None) if sniff::has_video_extension(&path) => {
+                    Err("Contents are not a video".to_owned())
+                }
+                Err(err) if sniff::has_video_extension(&path) => {
+                    Err(format!("Could not read the file: {}", err))
+                }
+                _ => return None,
+            };
+            let cache_key = match format {
+                Ok(_) => CacheKey::of(&path),
+                Err(_) => None,
+            };
+
+            Some(ScannedFile {
+                size: std::fs::metadata(&path).ok().map(|meta| meta.len()),
+                path,
+                format,
+                cache_key,
+            })
+        })
+        .collect()
 }
 
 pub fn main() {
-    let mut settings = Settings::default();
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
+    if !args.is_empty() {
+        std::process::exit(cli::run(args));
+    }
+
+    let flags = match config::read_settings() {
+        Ok(settings) => Flags {
+            settings,
+            settings_error: None,
+        },
+        Err(err) => {
+            eprintln!("Could not read settings: {}", err);
+
+            Flags {
+                settings: config::Settings::default(),
+                settings_error: Some(err.to_string()),
+            }
+        }
+    };
+    let window = flags.settings.window;
+    let mut settings = Settings::with_flags(flags);
+
+    settings.window.size = (window.width, window.height);
     settings.default_font = Some(include_bytes!("../fonts/SourceCodePro-Regular.ttf"));
 
-    App::run(settings)
+    #[cfg(feature = "fake-api")]
+    {
+        if std::env::var_os(FAKE_API_VAR).is_some() {
+            return App::<api::FakeApi>::run(settings);
+        }
+    }
+
+    App::<ApiClient>::run(settings)
+}
+
+/// The preset applied by Ctrl and a number key, counting from zero.
+fn preset_shortcut(key_code: KeyCode) -> Option<usize> {
+    let keys = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+
+    keys.iter().position(|key| *key == key_code)
+}
+
+#[derive(Debug, Default)]
+struct Flags {
+    settings: config::Settings,
+    /// Why the settings could not be read. The app then runs on the defaults
+    /// but leaves the settings file alone.
+    settings_error: Option<String>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -52,8 +188,8 @@ impl Default for FileSelection {
     }
 }
 
-#[derive(Debug, Default)]
-struct App {
+#[derive(Debug)]
+struct App<A: ArchiveApi> {
     id_counter: u64,
     hovering_with_files: bool,
     files: Vec<File>,
@@ -69,7 +205,17 @@ struct App {
 
     // API
     current_user: Option<User>,
-    current_config: Option<Config>,
+    api: Option<A>,
+    // Logged in, but not yet confirmed by the server.
+    connecting: Option<A>,
+    settings: config::Settings,
+    // The settings could not be read, so they must not be overwritten.
+    settings_read_only: bool,
+    // Changed without being written yet, e.g. while the window is resized.
+    settings_changed: bool,
+    hash_cache: HashCache,
+    // Changed since the session was last written?
+    session_changed: bool,
 
     // Scenes
     current_scene: Scenes,
@@ -84,13 +230,88 @@ struct App {
     left_control: bool,
 
     file_selection: FileSelection,
+    sort: Option<Sort>,
+    sort_headers: SortHeaders,
     enqueue_button: button::State,
+    remove_button: button::State,
+    remove_queued_button: button::State,
+    rehash_button: button::State,
     upload_button: button::State,
-    tag_input: text_input::State,
-    tags: String,
+    decrease_concurrency_button: button::State,
+    increase_concurrency_button: button::State,
+    upload_scheduler: UploadScheduler,
+    /// Files started since the queue was last empty, so completed ones keep
+    /// counting towards the queue's progress until all of them are done.
+    upload_batch: HashSet<u64>,
+    pause_button: button::State,
+    resume_button: button::State,
+    cancel_button: button::State,
+    pause_all_button: button::State,
+    resume_all_button: button::State,
+    cancel_all_button: button::State,
+    tag_editor: TagEditor,
+    preset_panel: PresetPanel,
+    source_input: text_input::State,
+    source: String,
+    description_input: text_input::State,
+    description: String,
 }
 
-impl App {
+impl<A: ArchiveApi> Default for App<A> {
+    // Derived, this would require `A: Default`.
+    fn default() -> Self {
+        Self {
+            id_counter: Default::default(),
+            hovering_with_files: Default::default(),
+            files: Default::default(),
+            file_scrollable: Default::default(),
+            current_filter: Default::default(),
+            pending_button: Default::default(),
+            queued_button: Default::default(),
+            duplicate_button: Default::default(),
+            completed_button: Default::default(),
+            failed_button: Default::default(),
+            current_user: Default::default(),
+            api: Default::default(),
+            connecting: Default::default(),
+            settings: Default::default(),
+            settings_read_only: Default::default(),
+            settings_changed: Default::default(),
+            hash_cache: Default::default(),
+            session_changed: Default::default(),
+            current_scene: Default::default(),
+            welcome_scene: Default::default(),
+            left_shift: Default::default(),
+            left_control: Default::default(),
+            file_selection: Default::default(),
+            sort: None,
+            sort_headers: Default::default(),
+            enqueue_button: Default::default(),
+            remove_button: Default::default(),
+            remove_queued_button: Default::default(),
+            rehash_button: Default::default(),
+            upload_button: Default::default(),
+            decrease_concurrency_button: Default::default(),
+            increase_concurrency_button: Default::default(),
+            upload_scheduler: Default::default(),
+            upload_batch: Default::default(),
+            pause_button: Default::default(),
+            resume_button: Default::default(),
+            cancel_button: Default::default(),
+            pause_all_button: Default::default(),
+            resume_all_button: Default::default(),
+            cancel_all_button: Default::default(),
+            tag_editor: Default::default(),
+            preset_panel: Default::default(),
+            source_input: Default::default(),
+            source: Default::default(),
+            description_input: Default::default(),
+            description: Default::default(),
+        }
+    }
+}
+
+impl<A: ArchiveApi> App<A> {
     fn get_id(&mut self) -> u64 {
         let id = self.id_counter;
         self.id_counter += 1;
@@ -101,44 +322,93 @@ impl App {
         self.files.iter().find(|file| &file.path == path).is_some()
     }
 
-    pub fn add_path(&mut self, path: PathBuf) -> Vec<Command<Message>> {
-        let mut commands = Vec::new();
-
-        if path.is_dir() {
-            for entry in WalkDir::new(path) {
-                let file_path = entry.unwrap().path().to_owned();
+    /// Looks for videos at `path` off the UI thread. They are added once
+    /// [`Message::PathScanned`] arrives.
+    pub fn add_path(&self, path: PathBuf) -> Command<Message> {
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || scan_path(path))
+                    .await
+                    .unwrap_or_default()
+            },
+            Message::PathScanned,
+        )
+    }
 
-                if is_video(&file_path) && !self.contains_path(&file_path) {
-                    let id = self.get_id();
+    /// Adds the files [`App::add_path`] found. Videos are hashed by the
+    /// subscription as workers free up, unless their hash is cached; files
+    /// that are not videos are added as invalid, to show why they are not
+    /// uploaded.
+    fn add_scanned(&mut self, scanned: Vec<ScannedFile>) {
+        let default_tags = match tags::parse_list(&self.settings.default_tags) {
+            Ok(default_tags) => default_tags,
+            Err(err) => {
+                eprintln!("Ignoring default tags: {}", err);
+                Vec::new()
+            }
+        };
+        let (rules, errors) = RuleSet::compile(&self.settings.rules);
 
-                    commands.push(Command::perform(
-                        File::analyze_file(id, file_path.clone()),
-                        move |result| Message::FileAnalyzed(id, result),
-                    ));
+        for err in errors {
+            eprintln!("Ignoring rule: {}", err);
+        }
 
-                    self.files.push(File {
-                        id,
-                        path: file_path,
-                        ..Default::default()
-                    });
-                }
+        for scanned in scanned {
+            if self.contains_path(&scanned.path) {
+                continue;
             }
-        } else if is_video(&path) && !self.contains_path(&path) {
-            let id = self.get_id();
 
-            commands.push(Command::perform(
-                File::analyze_file(id.clone(), path.clone()),
-                move |result| Message::FileAnalyzed(id, result),
-            ));
+            let id = self.get_id();
+            let cached_md5 = scanned
+                .cache_key
+                .and_then(|key| self.hash_cache.lookup(&key));
 
-            self.files.push(File {
+            let mut file = File {
                 id,
-                path,
+                size: scanned.size,
+                path: scanned.path,
+                tags: default_tags.clone(),
+                cached_md5,
                 ..Default::default()
-            });
+            };
+
+            match scanned.format {
+                Ok(format) => file.format = Some(format),
+                Err(reason) => {
+                    file.state = FileState::Invalid;
+                    file.invalid_reason = Some(reason);
+                }
+            }
+
+            rules.apply(&mut file);
+            self.files.push(file);
         }
 
-        commands
+        self.sort_files();
+    }
+
+    fn sort_files(&mut self) {
+        let sort = match self.sort {
+            Some(sort) => sort,
+            None => return,
+        };
+
+        let selected: Vec<u64> = self.selection().iter().map(|file| file.id).collect();
+        self.files.sort_by(|a, b| sort.compare(a, b));
+
+        if let FileSelection::Multiple(_) = self.file_selection {
+            let current_filter = self.current_filter;
+            let indices = self
+                .files
+                .iter()
+                .filter(|file| current_filter.states().contains(&file.state))
+                .enumerate()
+                .filter(|(_, file)| selected.contains(&file.id))
+                .map(|(idx, _)| idx)
+                .collect();
+
+            self.file_selection = FileSelection::Multiple(indices);
+        }
     }
 
     pub fn pending(&mut self) -> Vec<&mut File> {
@@ -173,14 +443,21 @@ impl App {
     pub fn failed(&mut self) -> Vec<&mut File> {
         self.files
             .iter_mut()
-            .filter(|file| file.state == FileState::Failed)
+            .filter(|file| Filter::Failed.states().contains(&file.state))
             .collect()
     }
 
     pub fn queued(&mut self) -> Vec<&mut File> {
         self.files
             .iter_mut()
-            .filter(|file| vec![FileState::Queued, FileState::Uploading].contains(&file.state))
+            .filter(|file| Filter::Queued.states().contains(&file.state))
+            .collect()
+    }
+
+    pub fn waiting(&mut self) -> Vec<&mut File> {
+        self.files
+            .iter_mut()
+            .filter(|file| file.state == FileState::Queued)
             .collect()
     }
 
@@ -191,7 +468,49 @@ impl App {
             .collect()
     }
 
-    pub fn get_tags_from_selection(&self) -> Option<String> {
+    pub fn selected_files(&mut self) -> Vec<&mut File> {
+        let current_filter = self.current_filter;
+        let indices = match &self.file_selection {
+            FileSelection::None => return Vec::new(),
+            FileSelection::Multiple(indices) => indices.clone(),
+        };
+
+        self.files
+            .iter_mut()
+            .filter(|file| current_filter.states().contains(&file.state))
+            .enumerate()
+            .filter(|(idx, _)| indices.contains(idx))
+            .map(|(_, file)| file)
+            .collect()
+    }
+
+    /// Pauses the given uploads. Dropping an upload's subscription aborts the
+    /// request in flight; chunked uploads later resume from their last part.
+    fn pause(files: Vec<&mut File>) {
+        for file in files {
+            if file.state == FileState::Queued || file.state == FileState::Uploading {
+                file.state = FileState::Paused;
+            }
+        }
+    }
+
+    fn resume(files: Vec<&mut File>) {
+        for file in files {
+            if file.state == FileState::Paused {
+                file.state = FileState::Queued;
+            }
+        }
+    }
+
+    fn cancel(files: Vec<&mut File>) {
+        for file in files {
+            file.state = FileState::Cancelled;
+            file.progress = None;
+            file.multipart = None;
+        }
+    }
+
+    pub fn selection(&self) -> Vec<&File> {
         let current_filter = self.current_filter;
         let files: Vec<&File> = self
             .files
@@ -201,9 +520,9 @@ impl App {
         let mut selected_files: Vec<&File> = Vec::new();
 
         match &self.file_selection {
-            FileSelection::None => return None,
+            FileSelection::None => return Vec::new(),
             FileSelection::Multiple(indices) => {
-                for (idx, file) in files.iter().enumerate() {
+                for (idx, file) in files.into_iter().enumerate() {
                     if indices.contains(&idx) {
                         selected_files.push(file);
                     }
@@ -211,54 +530,261 @@ impl App {
             }
         }
 
-        let mut tags: Option<String> = None;
+        selected_files
+    }
+
+    pub fn get_field_from_selection(&self, field: fn(&File) -> &String) -> Option<String> {
+        let mut value: Option<String> = None;
 
-        for (idx, file) in selected_files.iter().enumerate() {
+        for (idx, file) in self.selection().iter().enumerate() {
             if idx == 0 {
-                tags = Some(file.tags.clone());
+                value = Some(field(file).clone());
             }
 
-            if let Some(ref current_tags) = tags {
-                if current_tags != &file.tags {
-                    tags = None;
+            if let Some(ref current_value) = value {
+                if current_value != field(file) {
+                    value = None;
+                }
+            }
+        }
+
+        value
+    }
+
+    /// Fills the file form with the values shared by the selected files,
+    /// leaving fields with mixed values empty.
+    fn load_file_form(&mut self) {
+        self.load_tag_editor();
+        self.source = self
+            .get_field_from_selection(|file| &file.source)
+            .unwrap_or_default();
+        self.description = self
+            .get_field_from_selection(|file| &file.description)
+            .unwrap_or_default();
+    }
+
+    fn load_tag_editor(&mut self) {
+        let tags: Vec<Vec<Tag>> = self
+            .selection()
+            .iter()
+            .map(|file| file.tags.clone())
+            .collect();
+
+        self.tag_editor.load(&tags);
+    }
+
+    fn shared_tags(&self) -> Vec<Tag> {
+        let selection = self.selection();
+
+        match selection.split_first() {
+            Some((first, rest)) => first
+                .tags
+                .iter()
+                .filter(|tag| rest.iter().all(|file| file.tags.contains(tag)))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn apply_preset(&mut self, idx: usize) {
+        if let Some(preset) = self.settings.tag_presets.get(idx).cloned() {
+            self.update_selected_tags(|file_tags| {
+                for tag in &preset.tags {
+                    tags::add(file_tags, tag.clone());
+                }
+            });
+        }
+    }
+
+    fn update_selected_tags(&mut self, mut update: impl FnMut(&mut Vec<Tag>)) {
+        for file in self.selected_files() {
+            update(&mut file.tags);
+        }
+
+        self.load_tag_editor();
+        self.save_session();
+    }
+
+    pub fn queue_progress(&self) -> (u64, u64, f64) {
+        self.files
+            .iter()
+            .filter(|file| {
+                Filter::Queued.states().contains(&file.state)
+                    || (file.state == FileState::Completed && self.upload_batch.contains(&file.id))
+            })
+            .fold((0, 0, 0.0), |(sent, total, speed), file| {
+                let size = file.size.unwrap_or_default();
+
+                match (file.state, file.progress) {
+                    (FileState::Completed, _) => (sent + size, total + size, speed),
+                    (FileState::Uploading, Some(progress)) => (
+                        sent + progress.bytes_sent,
+                        total + size,
+                        speed + progress.speed(),
+                    ),
+                    (FileState::Paused, Some(progress)) => {
+                        (sent + progress.bytes_sent, total + size, speed)
+                    }
+                    _ => (sent, total + size, speed),
                 }
+            })
+    }
+
+    pub fn restore_session(&mut self, session: session::Session) {
+        for session_file in session.files {
+            if !session_file.path.is_file() || self.contains_path(&session_file.path) {
+                continue;
+            }
+
+            let id = self.get_id();
+            let mut file = session_file.into_file(id);
+
+            if file.state == FileState::Analyzing {
+                file.cached_md5 = self.hash_cache.get(&file.path);
             }
+
+            self.files.push(file);
+        }
+    }
+
+    pub fn save_settings(&mut self) {
+        self.settings_changed = false;
+
+        if self.settings_read_only {
+            return;
+        }
+
+        if let Err(err) = config::write_settings(&self.settings) {
+            eprintln!("Could not save settings: {}", err);
+        }
+    }
+
+    /// Marks the session as changed. It is written shortly after, once for
+    /// a whole burst of changes.
+    pub fn save_session(&mut self) {
+        self.session_changed = true;
+    }
+
+    fn write_session(&mut self) {
+        if !self.session_changed {
+            return;
         }
 
-        return tags;
+        self.session_changed = false;
+        let session = session::Session::new(&self.files);
+
+        if let Err(err) = session::write_session(&session) {
+            eprintln!("Could not save session: {}", err);
+        }
+    }
+
+    pub fn login(&mut self, profile: Profile) -> Command<Message> {
+        let api = match A::connect(
+            profile.config(),
+            &self.settings.network,
+            self.settings.requests_per_second,
+        ) {
+            Ok(api) => api,
+            Err(err) => {
+                return self
+                    .welcome_scene
+                    .update(WelcomeMessage::SetDisplayError(err.to_string()));
+            }
+        };
+
+        self.connecting = Some(api.clone());
+
+        Command::perform(
+            async move {
+                let response = api.me().await;
+                response.map(|user| (profile, user))
+            },
+            |resp| match resp {
+                Ok((profile, user)) => Message::LoggedIn(profile, user),
+                Err(e) => Message::WelcomeMessage(WelcomeMessage::SetDisplayError(e.to_string())),
+            },
+        )
+    }
+
+    /// Checks every analyzed file against the archive, once all files have
+    /// been analyzed and we are logged in.
+    pub fn check_duplicates(&self) -> Command<Message> {
+        let api = match self.api.clone() {
+            Some(api) => api,
+            None => return Command::none(),
+        };
+
+        let all_files_analyzed = self
+            .files
+            .iter()
+            .all(|file| file.state != FileState::Analyzing);
+
+        let checksums: Vec<String> = self
+            .files
+            .iter()
+            .filter(|file| file.state == FileState::Analyzed)
+            .map(|file| file.get_md5())
+            .collect();
+
+        if !all_files_analyzed || checksums.is_empty() {
+            return Command::none();
+        }
+
+        let requested_checksums = checksums.clone();
+
+        Command::perform(
+            async move { api.check_checksums(&requested_checksums).await },
+            move |response| match response {
+                Ok(duplicates) => Message::DuplicateCheckResponse(checksums.clone(), duplicates),
+                Err(_) => Message::Noop,
+            },
+        )
     }
 }
 
-impl Application for App {
+impl<A: ArchiveApi> Application for App<A> {
     type Executor = executor::Default;
     type Message = Message;
-    type Flags = ();
-
-    fn new(_flags: ()) -> (App, Command<Message>) {
-        let api_key = config::read_api_key().ok();
-
-        let cmd = match api_key {
-            Some(api_key) => {
-                let config = api::Config::new(api_key.clone());
-
-                Command::perform(
-                    async move {
-                        let config = api::Config::new(api_key.clone());
-                        let response = api::User::get(&config).await;
-                        response
-                    },
-                    move |resp| match resp {
-                        Ok(user) => Message::SetConfigAndUser(config.clone(), user),
-                        Err(e) => Message::WelcomeMessage(scenes::WelcomeMessage::SetDisplayError(
-                            e.to_string(),
-                        )),
-                    },
-                )
-            }
+    type Flags = Flags;
+
+    fn new(flags: Flags) -> (Self, Command<Message>) {
+        let settings = flags.settings;
+        let mut app = Self {
+            settings_read_only: flags.settings_error.is_some(),
+            welcome_scene: WelcomeScene::new(&settings.profiles),
+            hash_cache: HashCache::load(),
+            ..Self::default()
+        };
+
+        app.upload_scheduler
+            .set_concurrency(settings.upload_concurrency);
+
+        let active_profile = settings.profiles.active_profile().cloned();
+        app.settings = settings;
+
+        let mut cmd = match active_profile {
+            Some(profile) => app.login(profile),
             None => Command::none(),
         };
 
-        (App::default(), cmd)
+        if let Some(err) = flags.settings_error {
+            // Without settings there is no profile, so this shows right away.
+            cmd = app
+                .welcome_scene
+                .update(WelcomeMessage::SetDisplayError(format!(
+                    "Could not read settings, changes will not be saved: {}",
+                    err
+                )));
+        }
+
+        match session::read_session() {
+            Ok(Some(session)) => app.restore_session(session),
+            Ok(None) => {}
+            Err(err) => eprintln!("Could not restore the session: {}", err),
+        }
+
+        (app, cmd)
     }
 
     fn title(&self) -> String {
@@ -274,12 +800,15 @@ impl Application for App {
                 Event::Window(WindowEvent::FilesHoveredLeft) => {
                     self.hovering_with_files = false;
                 }
+                Event::Window(WindowEvent::Resized { width, height }) => {
+                    self.settings.window = config::WindowGeometry { width, height };
+                    self.settings_changed = true;
+                }
                 Event::Window(WindowEvent::FileDropped(path)) => {
                     if self.current_scene == Scenes::FileIndex {
                         self.hovering_with_files = false;
-                        let commands = self.add_path(path);
 
-                        return Command::batch(commands);
+                        return self.add_path(path);
                     }
                 }
                 Event::Keyboard(KeyboardEvent::Input {
@@ -303,60 +832,120 @@ impl Application for App {
                                 self.left_control = false;
                             }
                         }
+                    } else if state == ButtonState::Pressed && self.left_control {
+                        if let Some(idx) = preset_shortcut(key_code) {
+                            self.apply_preset(idx);
+                        }
+                    } else if state == ButtonState::Pressed {
+                        self.tag_editor.key_pressed(key_code);
                     }
                 }
                 _ => {}
             },
+            Message::PathScanned(scanned) => {
+                self.add_scanned(scanned);
+                self.save_session();
+            }
             Message::FileAnalyzed(id, result) => match result {
                 Ok(analysis) => {
                     if let Some(file) = self.files.iter_mut().find(|file| file.id == id) {
+                        // Cached hashes are already stored.
+                        let fingerprint = match file.cached_md5 {
+                            Some(_) => None,
+                            None => analysis.fingerprint().cloned(),
+                        };
                         file.update(FileMessage::Analyzed(analysis));
+
+                        if let (Some(fingerprint), Some(md5)) = (fingerprint, file.md5) {
+                            self.hash_cache.insert(&file.path, fingerprint, md5);
+                        }
                     }
 
-                    let all_files_analyzed = self
+                    if self
                         .files
                         .iter()
-                        .all(|file| file.state != FileState::Analyzing);
-
-                    if all_files_analyzed {
-                        let checksums: Vec<String> = self
-                            .files
-                            .iter()
-                            .filter(|file| file.state == FileState::Analyzed)
-                            .map(|file| file.get_md5())
-                            .collect();
-                        let requested_checksums = checksums.clone();
-                        let config = self.current_config.clone().unwrap();
-
-                        return Command::perform(
-                            async move { api::Checksums::check(&requested_checksums, &config).await },
-                            move |response| match response {
-                                Ok(response) => Message::DuplicateCheckResponse(
-                                    checksums.clone(),
-                                    response.checksums,
-                                ),
-                                Err(_) => Message::Noop,
-                            },
-                        );
+                        .all(|file| file.state != FileState::Analyzing)
+                    {
+                        self.hash_cache.save();
+                    }
+
+                    self.sort_files();
+                    self.save_session();
+
+                    return self.check_duplicates();
+                }
+                Err(err) => {
+                    // Held back like a broken file, so it does not keep a
+                    // hashing worker busy.
+                    if let Some(file) = self.files.iter_mut().find(|file| file.id == id) {
+                        file.state = FileState::Invalid;
+                        file.hashed_bytes = None;
+                        file.invalid_reason = Some(err.to_string());
                     }
+
+                    self.save_session();
+
+                    return self.check_duplicates();
                 }
-                Err(_) => {}
             },
+            Message::SortBy(column) => {
+                // Clicking the sorted column again reverses the order.
+                let descending = self.sort
+                    == Some(Sort {
+                        column,
+                        descending: false,
+                    });
+
+                self.sort = Some(Sort { column, descending });
+                self.sort_files();
+                self.save_session();
+            }
+            Message::SaveSession => self.write_session(),
+            Message::SaveSettings => {
+                if self.settings_changed {
+                    self.save_settings();
+                }
+            }
             Message::Noop => {}
             Message::FileMessage(id, message) => {
+                let persist = !matches!(message, FileMessage::Uploaded(_) | FileMessage::Hashed(_));
+
                 if let Some(file) = self.files.iter_mut().find(|file| file.id == id) {
                     file.update(message);
                 }
+
+                if persist {
+                    self.save_session();
+                }
             }
             Message::WelcomeMessage(msg) => {
                 return self.welcome_scene.update(msg);
             }
-            Message::SetConfigAndUser(config, user) => {
-                let _ = config::write_api_key(&config.api_token);
+            Message::Login(profile) => {
+                return self.login(profile);
+            }
+            Message::LoggedIn(profile, user) => {
+                self.api = self.connecting.take();
+
+                if let Err(err) = config::write_api_key(&profile) {
+                    eprintln!("Could not save API key: {}", err);
+                }
+
+                self.settings.profiles.activate(profile);
+                self.save_settings();
 
-                self.current_config = Some(config);
                 self.current_user = Some(user);
                 self.current_scene = Scenes::FileIndex;
+
+                return self.check_duplicates();
+            }
+            Message::ForgetProfile(name) => {
+                if let Err(err) = config::delete_api_key(&name) {
+                    eprintln!("Could not delete API key: {}", err);
+                }
+
+                self.settings.profiles.remove(&name);
+                self.save_settings();
             }
             Message::DuplicateCheckResponse(checksums, duplicate_checksums) => {
                 for file in self.files.iter_mut() {
@@ -378,6 +967,8 @@ impl Application for App {
                         }
                     }
                 }
+
+                self.save_session();
             }
             Message::SetFilter(filter) => {
                 self.current_filter = filter;
@@ -386,6 +977,7 @@ impl Application for App {
             Message::SelectFile(selected_idx) => match &self.file_selection {
                 FileSelection::None => {
                     self.file_selection = FileSelection::Multiple(vec![selected_idx]);
+                    self.load_file_form();
                 }
                 FileSelection::Multiple(indices) => {
                     if self.left_shift {
@@ -412,30 +1004,106 @@ impl Application for App {
                         self.file_selection = FileSelection::Multiple(vec![selected_idx]);
                     }
 
-                    self.tags = self.get_tags_from_selection().unwrap_or_default();
+                    self.load_file_form();
                 }
             },
-            Message::SetTags(tags) => {
-                let new_tags = tags.clone();
-                self.tags = tags;
-                let current_filter = self.current_filter;
-                let mut files: Vec<&mut File> = self
-                    .files
-                    .iter_mut()
-                    .filter(|file| current_filter.states().contains(&file.state))
-                    .collect();
-                match &self.file_selection {
-                    FileSelection::None => return Command::none(),
-                    FileSelection::Multiple(indices) => {
-                        for (idx, file) in files.iter_mut().enumerate() {
-                            if indices.contains(&idx) {
-                                file.tags = new_tags.clone();
-                            }
+            Message::TagMessage(TagMessage::InputChanged(value)) => {
+                if let Some(generation) = self.tag_editor.set_input(value) {
+                    return Command::perform(delay_for(widgets::tags::SEARCH_DELAY), move |_| {
+                        Message::TagMessage(TagMessage::Search(generation))
+                    });
+                }
+            }
+            Message::TagMessage(TagMessage::Search(generation)) => {
+                let api = self.api.clone();
+                let query = self.tag_editor.query_for(generation);
+
+                if let (Some(api), Some(query)) = (api, query) {
+                    return Command::perform(
+                        async move {
+                            let result = api.search_tags(&query).await;
+                            (query, result)
+                        },
+                        |(query, result)| {
+                            Message::TagMessage(TagMessage::Suggestions(query, result))
+                        },
+                    );
+                }
+            }
+            Message::TagMessage(TagMessage::Suggestions(query, result)) => match result {
+                Ok(suggestions) => self.tag_editor.set_suggestions(query, suggestions),
+                Err(err) => eprintln!("Could not search tags: {}", err),
+            },
+            Message::TagMessage(TagMessage::AcceptSuggestion(idx)) => {
+                self.tag_editor.accept(idx);
+            }
+            Message::TagMessage(TagMessage::Submit) => {
+                if self.tag_editor.accept_highlighted() {
+                    return Command::none();
+                }
+
+                if let Some(new_tags) = self.tag_editor.take_input() {
+                    self.update_selected_tags(|file_tags| {
+                        for tag in &new_tags {
+                            tags::add(file_tags, tag.clone());
                         }
+                    });
+                }
+            }
+            Message::TagMessage(TagMessage::AddToAll(tag)) => {
+                self.update_selected_tags(|file_tags| tags::add(file_tags, tag.clone()));
+            }
+            Message::TagMessage(TagMessage::Remove(tag)) => {
+                self.update_selected_tags(|file_tags| {
+                    file_tags.retain(|file_tag| file_tag != &tag)
+                });
+            }
+            Message::PresetMessage(PresetMessage::Apply(idx)) => {
+                self.apply_preset(idx);
+            }
+            Message::PresetMessage(PresetMessage::Delete(idx)) => {
+                if idx < self.settings.tag_presets.len() {
+                    self.settings.tag_presets.remove(idx);
+                    self.save_settings();
+                }
+            }
+            Message::PresetMessage(PresetMessage::NameChanged(name)) => {
+                self.preset_panel.set_name(name);
+            }
+            Message::PresetMessage(PresetMessage::Save) => {
+                let tags = self.shared_tags();
 
-                        return Command::none();
+                if tags.is_empty() {
+                    return Command::none();
+                }
+
+                if let Some(name) = self.preset_panel.take_name() {
+                    let preset = config::TagPreset { name, tags };
+                    let presets = &mut self.settings.tag_presets;
+
+                    match presets.iter_mut().find(|p| p.name == preset.name) {
+                        Some(existing) => *existing = preset,
+                        None => presets.push(preset),
                     }
+
+                    self.save_settings();
+                }
+            }
+            Message::SetSource(source) => {
+                for file in self.selected_files() {
+                    file.source = source.clone();
+                }
+
+                self.source = source;
+                self.save_session();
+            }
+            Message::SetDescription(description) => {
+                for file in self.selected_files() {
+                    file.description = description.clone();
                 }
+
+                self.description = description;
+                self.save_session();
             }
             Message::Enqueue => {
                 let current_filter = self.current_filter;
@@ -448,75 +1116,165 @@ impl Application for App {
                     FileSelection::None => return Command::none(),
                     FileSelection::Multiple(indices) => {
                         for (idx, file) in files.iter_mut().enumerate() {
-                            if indices.contains(&idx) {
+                            // Invalid files are listed with the failed ones
+                            // but can never be uploaded.
+                            if indices.contains(&idx) && file.state != FileState::Invalid {
                                 file.state = FileState::Queued;
+                                file.error = None;
                             }
                         }
 
-                        self.tags = "".to_string();
+                        self.tag_editor.clear();
+                        self.source = "".to_string();
+                        self.description = "".to_string();
                         self.file_selection = FileSelection::None;
+                        self.save_session();
 
                         return Command::none();
                     }
                 }
             }
             Message::StartUpload => {
-                for file in self.queued().iter_mut() {
+                self.upload_scheduler.start();
+
+                return self.update(Message::BeginUploadBatch);
+            }
+            Message::BeginUploadBatch => {
+                let in_flight = self.uploading().len();
+                let available_slots = self.upload_scheduler.available_slots(in_flight);
+
+                if in_flight == 0 && self.waiting().is_empty() {
+                    self.upload_scheduler.stop();
+                }
+
+                let batch_done = self
+                    .files
+                    .iter()
+                    .filter(|file| self.upload_batch.contains(&file.id))
+                    .all(|file| !Filter::Queued.states().contains(&file.state));
+                if batch_done {
+                    self.upload_batch.clear();
+                }
+
+                // The uploads themselves are driven by `subscription`.
+                let mut started = Vec::new();
+                for file in self.waiting().into_iter().take(available_slots) {
+                    started.push(file.id);
                     file.state = FileState::Uploading;
+                    file.progress = Some(UploadProgress::new(file.size.unwrap_or_default()));
                 }
+                self.upload_batch.extend(started);
+
+                self.save_session();
+            }
+            Message::SetUploadConcurrency(concurrency) => {
+                self.upload_scheduler.set_concurrency(concurrency);
+                self.settings.upload_concurrency = self.upload_scheduler.concurrency();
+                self.save_settings();
 
                 return self.update(Message::BeginUploadBatch);
             }
-            Message::BeginUploadBatch => {
-                let config = self.current_config.clone().unwrap();
+            Message::PauseSelected => {
+                Self::pause(self.selected_files());
+                self.save_session();
 
-                let commands: Vec<Command<Message>> = self
-                    .uploading()
-                    .iter_mut()
-                    .take(1)
-                    .map(|file| {
-                        let id = file.id.clone();
-                        let path = file.path.clone();
-                        let md5 = file.get_md5();
-                        let tags = file.tags.clone();
-                        let api_config = config.clone();
-
-                        file.state = FileState::Uploading;
-
-                        Command::perform(
-                            async move {
-                                let result = api::Upload::new(&api_config, &path, &md5).await?;
-                                let _ = api::Upload::upload_file(&path, &result.url).await?;
-                                let _ =
-                                    api::Upload::finalize(&api_config, &result.id, &tags, "", "")
-                                        .await?;
-
-                                Ok(())
-                            },
-                            move |response: Result<(), api::ApiError>| match response {
-                                Ok(_) => Message::SuccessfulUpload(id),
-                                Err(err) => {
-                                    dbg!(err);
-                                    Message::FailedUpload(id)
-                                }
-                            },
-                        )
-                    })
+                return self.update(Message::BeginUploadBatch);
+            }
+            Message::ResumeSelected => {
+                Self::resume(self.selected_files());
+                self.upload_scheduler.start();
+
+                return self.update(Message::BeginUploadBatch);
+            }
+            Message::CancelSelected => {
+                Self::cancel(self.selected_files());
+                self.file_selection = FileSelection::None;
+                self.save_session();
+
+                return self.update(Message::BeginUploadBatch);
+            }
+            Message::RemoveSelected => {
+                let removed: Vec<u64> = self.selection().iter().map(|file| file.id).collect();
+
+                // Hashes and uploads of removed files stop along with their
+                // subscriptions.
+                self.files.retain(|file| !removed.contains(&file.id));
+                self.file_selection = FileSelection::None;
+                self.tag_editor.clear();
+                self.save_session();
+
+                return Command::batch(vec![
+                    self.check_duplicates(),
+                    self.update(Message::BeginUploadBatch),
+                ]);
+            }
+            Message::RehashSelected => {
+                let rehashed: Vec<u64> = self
+                    .selection()
+                    .iter()
+                    .filter(|file| REHASHABLE_STATES.contains(&file.state))
+                    .map(|file| file.id)
                     .collect();
 
-                return Command::batch(commands);
+                for file in self
+                    .files
+                    .iter_mut()
+                    .filter(|file| rehashed.contains(&file.id))
+                {
+                    self.hash_cache.remove(&file.path);
+
+                    file.state = FileState::Analyzing;
+                    file.md5 = None;
+                    file.cached_md5 = None;
+                    file.hashed_bytes = None;
+                    file.invalid_reason = None;
+                    file.size = std::fs::metadata(&file.path).ok().map(|meta| meta.len());
+                }
+
+                self.hash_cache.save();
+                self.file_selection = FileSelection::None;
+                self.tag_editor.clear();
+                self.save_session();
+            }
+            Message::PauseAll => {
+                self.upload_scheduler.stop();
+                Self::pause(self.queued());
+                self.save_session();
+            }
+            Message::ResumeAll => {
+                Self::resume(self.queued());
+                self.upload_scheduler.start();
+
+                return self.update(Message::BeginUploadBatch);
+            }
+            Message::CancelAll => {
+                self.upload_scheduler.stop();
+                Self::cancel(self.queued());
+                self.file_selection = FileSelection::None;
+                self.save_session();
             }
             Message::SuccessfulUpload(id) => {
                 if let Some(file) = self.files.iter_mut().find(|file| file.id == id) {
                     file.state = FileState::Completed;
+                    file.progress = None;
+                    file.multipart = None;
+                    file.error = None;
                 }
 
+                self.save_session();
+
                 return self.update(Message::BeginUploadBatch);
             }
-            Message::FailedUpload(id) => {
+            Message::FailedUpload(id, error) => {
                 if let Some(file) = self.files.iter_mut().find(|file| file.id == id) {
                     file.state = FileState::Failed;
+                    file.progress = None;
+                    file.error = Some(error);
                 }
+
+                self.save_session();
+
+                return self.update(Message::BeginUploadBatch);
             }
         };
 
@@ -524,7 +1282,40 @@ impl Application for App {
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        iced_native::subscription::events().map(Message::EventOccurred)
+        let events = iced_native::subscription::events().map(Message::EventOccurred);
+
+        let hashes = hashing::next_batch(&self.files, self.settings.hash_workers)
+            .into_iter()
+            .map(|file| Subscription::from_recipe(FileHash::new(file)));
+
+        let uploads = match &self.api {
+            Some(api) => self
+                .files
+                .iter()
+                .filter(|file| file.state == FileState::Uploading)
+                .map(|file| Subscription::from_recipe(FileUpload::new(api, file)))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let save_session = if self.session_changed {
+            Some(Subscription::from_recipe(SaveTimer(Message::SaveSession)))
+        } else {
+            None
+        };
+        let save_settings = if self.settings_changed {
+            Some(Subscription::from_recipe(SaveTimer(Message::SaveSettings)))
+        } else {
+            None
+        };
+
+        Subscription::batch(
+            std::iter::once(events)
+                .chain(hashes)
+                .chain(uploads)
+                .chain(save_session)
+                .chain(save_settings),
+        )
     }
 
     fn view(&mut self) -> Element<Message> {
@@ -536,6 +1327,7 @@ impl Application for App {
                 let completed_count = self.completed().len();
                 let duplicate_count = self.duplicate().len();
                 let failed_count = self.failed().len();
+                let (bytes_sent, total_bytes, speed) = self.queue_progress();
 
                 let is_empty = self.files.is_empty();
 
@@ -546,7 +1338,12 @@ impl Application for App {
                     .filter(|file| current_filter.states().contains(&file.state))
                     .collect();
 
-                let file_index = file::file_index(&self.file_selection, files);
+                let file_index = file::file_index(
+                    &self.file_selection,
+                    self.sort,
+                    &mut self.sort_headers,
+                    files,
+                );
 
                 let file_scroll_view = Scrollable::new(&mut self.file_scrollable)
                     .width(Length::Fill)
@@ -568,10 +1365,20 @@ impl Application for App {
                 let file_form = Row::new()
                     .push(
                         TextInput::new(
-                            &mut self.tag_input,
-                            "Enter in tags",
-                            &self.tags,
-                            Message::SetTags,
+                            &mut self.source_input,
+                            "Source URL",
+                            &self.source,
+                            Message::SetSource,
+                        )
+                        .style(styles::TextInput::Primary)
+                        .width(Length::Fill),
+                    )
+                    .push(
+                        TextInput::new(
+                            &mut self.description_input,
+                            "Description",
+                            &self.description,
+                            Message::SetDescription,
                         )
                         .style(styles::TextInput::Primary)
                         .width(Length::Fill),
@@ -581,6 +1388,18 @@ impl Application for App {
                             .style(styles::Button::Transparent)
                             .padding(2)
                             .on_press(Message::Enqueue),
+                    )
+                    .push(
+                        Button::new(&mut self.remove_button, styles::text("Remove"))
+                            .style(styles::Button::Transparent)
+                            .padding(2)
+                            .on_press(Message::RemoveSelected),
+                    )
+                    .push(
+                        Button::new(&mut self.rehash_button, styles::text("Rehash"))
+                            .style(styles::Button::Transparent)
+                            .padding(2)
+                            .on_press(Message::RehashSelected),
                     );
 
                 if self.current_filter != Filter::Queued {
@@ -591,12 +1410,116 @@ impl Application for App {
                         FileSelection::None => bottom_bar,
                     };
                 } else {
-                    bottom_bar = bottom_bar.push(
-                        Button::new(&mut self.upload_button, styles::text("Upload All"))
-                            .style(styles::Button::Transparent)
-                            .on_press(Message::StartUpload)
-                            .padding(2),
-                    );
+                    let concurrency = self.upload_scheduler.concurrency();
+                    let percent = if total_bytes > 0 {
+                        bytes_sent as f32 / total_bytes as f32 * 100.0
+                    } else {
+                        0.0
+                    };
+                    let eta = if speed > 0.0 {
+                        file::format_duration(Duration::from_secs_f64(
+                            total_bytes.saturating_sub(bytes_sent) as f64 / speed,
+                        ))
+                    } else {
+                        "-".to_string()
+                    };
+
+                    bottom_bar = bottom_bar
+                        .push(
+                            ProgressBar::new(0.0..=100.0, percent)
+                                .width(Length::Units(150))
+                                .height(Length::Units(14))
+                                .style(styles::ProgressBar),
+                        )
+                        .push(
+                            styles::text(format!(
+                                "{} / {} ({}/s, ETA {})",
+                                file::format_bytes(bytes_sent),
+                                file::format_bytes(total_bytes),
+                                file::format_bytes(speed as u64),
+                                eta
+                            ))
+                            .height(Length::Fill)
+                            .vertical_alignment(VerticalAlignment::Center),
+                        );
+
+                    if let FileSelection::Multiple(ref indices) = self.file_selection {
+                        bottom_bar = bottom_bar
+                            .push(styles::text(format!("{} files selected", indices.len())))
+                            .push(
+                                Button::new(&mut self.pause_button, styles::text("Pause"))
+                                    .style(styles::Button::Transparent)
+                                    .on_press(Message::PauseSelected)
+                                    .padding(2),
+                            )
+                            .push(
+                                Button::new(&mut self.resume_button, styles::text("Resume"))
+                                    .style(styles::Button::Transparent)
+                                    .on_press(Message::ResumeSelected)
+                                    .padding(2),
+                            )
+                            .push(
+                                Button::new(&mut self.cancel_button, styles::text("Cancel"))
+                                    .style(styles::Button::Transparent)
+                                    .on_press(Message::CancelSelected)
+                                    .padding(2),
+                            )
+                            .push(
+                                Button::new(&mut self.remove_queued_button, styles::text("Remove"))
+                                    .style(styles::Button::Transparent)
+                                    .on_press(Message::RemoveSelected)
+                                    .padding(2),
+                            );
+                    }
+
+                    bottom_bar = bottom_bar
+                        .push(
+                            styles::text(format!("Parallel uploads: {}", concurrency))
+                                .height(Length::Fill)
+                                .vertical_alignment(VerticalAlignment::Center),
+                        )
+                        .push(
+                            Button::new(&mut self.decrease_concurrency_button, styles::text("-"))
+                                .style(styles::Button::Transparent)
+                                .on_press(Message::SetUploadConcurrency(
+                                    concurrency.saturating_sub(1),
+                                ))
+                                .padding(2),
+                        )
+                        .push(
+                            Button::new(&mut self.increase_concurrency_button, styles::text("+"))
+                                .style(styles::Button::Transparent)
+                                .on_press(Message::SetUploadConcurrency(concurrency + 1))
+                                .padding(2),
+                        )
+                        .push(if self.upload_scheduler.is_running() {
+                            Button::new(&mut self.upload_button, styles::text("Uploading..."))
+                                .style(styles::Button::Transparent)
+                                .padding(2)
+                        } else {
+                            Button::new(&mut self.upload_button, styles::text("Upload All"))
+                                .style(styles::Button::Transparent)
+                                .on_press(Message::StartUpload)
+                                .padding(2)
+                        })
+                        .push(
+                            Button::new(&mut self.pause_all_button, styles::text("Pause All"))
+                                .style(styles::Button::Transparent)
+                                .on_press(Message::PauseAll)
+                                .padding(2),
+                        )
+                        .push(
+                            Button::new(&mut self.resume_all_button, styles::text("Resume All"))
+                                .style(styles::Button::Transparent)
+                                .on_press(Message::ResumeAll)
+                                .padding(2),
+                        )
+                        .push(
+                            Button::new(&mut self.cancel_all_button, styles::text("Cancel All"))
+                                .style(styles::Button::Transparent)
+                                .on_press(Message::CancelAll)
+                                .padding(2),
+                        );
                 }
 
                 let bottom_bar_container = Container::new(bottom_bar)
@@ -650,6 +1573,23 @@ impl Application for App {
                         .style(styles::Button::Transparent),
                     );
 
+                let show_tag_editor = self.current_filter != Filter::Queued
+                    && self.file_selection != FileSelection::None;
+
+                let tag_bar = Container::new(self.tag_editor.view().map(Message::TagMessage))
+                    .width(Length::Fill)
+                    .padding(6)
+                    .style(styles::Container::Secondary);
+
+                let preset_bar = Container::new(
+                    self.preset_panel
+                        .view(&self.settings.tag_presets)
+                        .map(Message::PresetMessage),
+                )
+                .width(Length::Fill)
+                .padding(6)
+                .style(styles::Container::Secondary);
+
                 let top_view = Row::new()
                     .width(Length::Fill)
                     .height(Length::Fill)
@@ -670,12 +1610,17 @@ impl Application for App {
                         .push(bottom_bar_container)
                         .align_items(Align::Center)
                 } else {
-                    Column::new()
+                    let mut column = Column::new()
                         .width(Length::Fill)
                         .height(Length::Fill)
                         .push(filter_bar)
-                        .push(top_view)
-                        .push(bottom_bar_container)
+                        .push(top_view);
+
+                    if show_tag_editor {
+                        column = column.push(preset_bar).push(tag_bar);
+                    }
+
+                    column.push(bottom_bar_container)
                 };
 
                 Container::new(content)
@@ -689,3 +1634,281 @@ impl Application for App {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::time::Duration;
+
+    use futures::stream::{self, StreamExt};
+    use iced_native::subscription::Recipe;
+
+    use super::*;
+    use api::{ApiError, FakeApi};
+
+    fn app(api: FakeApi) -> App<FakeApi> {
+        App {
+            api: Some(api),
+            current_scene: Scenes::FileIndex,
+            ..App::default()
+        }
+    }
+
+    /// Runs `command` and every command the messages it produces lead to,
+    /// like the runtime does.
+    async fn run(app: &mut App<FakeApi>, command: Command<Message>) {
+        let mut pending = command.futures();
+
+        while !pending.is_empty() {
+            let message = pending.remove(0).await;
+            pending.extend(app.update(message).futures());
+        }
+    }
+
+    /// Adds a file and analyzes it like the hashing subscription does.
+    async fn add_file(
+        app: &mut App<FakeApi>,
+        dir: &tempfile::TempDir,
+        name: &str,
+        contents: &[u8],
+    ) -> u64 {
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+
+        let id = app.get_id();
+        app.files.push(File {
+            id,
+            path: path.clone(),
+            ..Default::default()
+        });
+
+        let result = File::analyze_file(id, path, None, |_| {}).await;
+        let command = app.update(Message::FileAnalyzed(id, result));
+        run(app, command).await;
+
+        id
+    }
+
+    /// Runs the uploads the subscription would start until none are left.
+    async fn run_uploads(app: &mut App<FakeApi>) {
+        loop {
+            let api = app.api.clone().unwrap();
+            let uploads: Vec<FileUpload<FakeApi>> = app
+                .files
+                .iter()
+                .filter(|file| file.state == FileState::Uploading)
+                .map(|file| FileUpload::new(&api, file))
+                .collect();
+
+            if uploads.is_empty() {
+                break;
+            }
+
+            for upload in uploads {
+                let messages: Vec<Message> =
+                    Recipe::<DefaultHasher, ()>::stream(Box::new(upload), stream::empty().boxed())
+                        .collect()
+                        .await;
+
+                for message in messages {
+                    let command = app.update(message);
+                    run(app, command).await;
+                }
+            }
+        }
+    }
+
+    fn file(app: &App<FakeApi>, id: u64) -> &File {
+        app.files.iter().find(|file| file.id == id).unwrap()
+    }
+
+    /// Queues every pending file and starts uploading.
+    async fn upload_pending(app: &mut App<FakeApi>) {
+        app.current_filter = Filter::Pending;
+        app.file_selection = FileSelection::Multiple((0..app.pending().len()).collect());
+
+        let command = app.update(Message::Enqueue);
+        run(app, command).await;
+        let command = app.update(Message::StartUpload);
+        run(app, command).await;
+
+        run_uploads(app).await;
+    }
+
+    #[test]
+    fn resizing_saves_settings_once() {
+        let mut app = app(FakeApi::new(Duration::from_millis(0), None));
+        // Keeps the test away from the real settings file.
+        app.settings_read_only = true;
+
+        for width in 800..900 {
+            let _ = app.update(Message::EventOccurred(Event::Window(
+                WindowEvent::Resized { width, height: 600 },
+            )));
+        }
+
+        assert!(app.settings_changed);
+        assert_eq!(app.settings.window.width, 899);
+
+        let _ = app.update(Message::SaveSettings);
+        assert!(!app.settings_changed);
+    }
+
+    #[tokio::test]
+    async fn dropped_folders_are_scanned_for_videos() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("clip"), b"\0\0\0\x18ftypisom\0\0\0\0").unwrap();
+        std::fs::write(dir.path().join("page.mp4"), b"<!DOCTYPE html>").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"notes").unwrap();
+
+        let mut app = app(FakeApi::new(Duration::from_millis(0), None));
+        let command = app.add_path(dir.path().to_owned());
+        // Nothing is added until the scan is done.
+        assert!(app.files.is_empty());
+
+        run(&mut app, command).await;
+
+        let mut names: Vec<&str> = app.files.iter().map(|file| file.file_name()).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["clip", "page.mp4"]);
+
+        let clip = app
+            .files
+            .iter()
+            .find(|file| file.file_name() == "clip")
+            .unwrap();
+        assert_eq!(clip.state, FileState::Analyzing);
+        assert_eq!(clip.format, Some(VideoFormat::Mp4));
+        assert_eq!(clip.size, Some(16));
+
+        let page = app
+            .files
+            .iter()
+            .find(|file| file.file_name() == "page.mp4")
+            .unwrap();
+        assert_eq!(page.state, FileState::Invalid);
+        assert_eq!(
+            page.invalid_reason.as_deref(),
+            Some("Contents are not a video")
+        );
+    }
+
+    #[tokio::test]
+    async fn analyzed_files_are_checked_for_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let api = FakeApi::new(Duration::from_millis(0), None);
+        let mut app = app(api.clone());
+
+        let first = add_file(&mut app, &dir, "first.mp4", b"first").await;
+        assert_eq!(file(&app, first).state, FileState::Pending);
+
+        upload_pending(&mut app).await;
+        assert_eq!(file(&app, first).state, FileState::Completed);
+
+        let copy = add_file(&mut app, &dir, "copy.mp4", b"first").await;
+        let other = add_file(&mut app, &dir, "other.mp4", b"other").await;
+        assert_eq!(file(&app, copy).state, FileState::Duplicate);
+        assert_eq!(file(&app, other).state, FileState::Pending);
+    }
+
+    #[tokio::test]
+    async fn enqueued_files_are_uploaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let api = FakeApi::new(Duration::from_millis(0), None);
+        let mut app = app(api.clone());
+
+        let ids = [
+            add_file(&mut app, &dir, "one.mp4", b"one").await,
+            add_file(&mut app, &dir, "two.mp4", b"two").await,
+            add_file(&mut app, &dir, "three.mp4", b"three").await,
+        ];
+
+        app.current_filter = Filter::Pending;
+        app.file_selection = FileSelection::Multiple(vec![0, 2]);
+        let command = app.update(Message::Enqueue);
+        run(&mut app, command).await;
+
+        assert_eq!(app.queued().len(), 2);
+        assert_eq!(file(&app, ids[1]).state, FileState::Pending);
+
+        let command = app.update(Message::StartUpload);
+        run(&mut app, command).await;
+        assert_eq!(app.uploading().len(), 2);
+
+        run_uploads(&mut app).await;
+
+        let checksums: Vec<String> = ids.iter().map(|id| file(&app, *id).get_md5()).collect();
+        assert_eq!(file(&app, ids[0]).state, FileState::Completed);
+        assert_eq!(file(&app, ids[1]).state, FileState::Pending);
+        assert_eq!(file(&app, ids[2]).state, FileState::Completed);
+        assert_eq!(
+            api.check_checksums(&checksums).await.unwrap(),
+            vec![checksums[0].clone(), checksums[2].clone()]
+        );
+        assert!(!app.upload_scheduler.is_running());
+    }
+
+    #[tokio::test]
+    async fn queue_progress_covers_the_whole_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app(FakeApi::new(Duration::from_millis(0), None));
+
+        let first = add_file(&mut app, &dir, "first.mp4", b"first").await;
+        let second = add_file(&mut app, &dir, "second.mp4", b"second").await;
+
+        app.current_filter = Filter::Pending;
+        app.file_selection = FileSelection::Multiple(vec![0, 1]);
+        let command = app.update(Message::Enqueue);
+        run(&mut app, command).await;
+        let command = app.update(Message::StartUpload);
+        run(&mut app, command).await;
+
+        let _ = app.update(Message::FileMessage(second, FileMessage::Uploaded(4)));
+        assert_eq!(app.queue_progress().0, 4);
+        assert_eq!(app.queue_progress().1, 11);
+
+        let command = app.update(Message::SuccessfulUpload(first));
+        run(&mut app, command).await;
+        assert_eq!(app.queue_progress().0, 9);
+        assert_eq!(app.queue_progress().1, 11);
+
+        app.current_filter = Filter::Queued;
+        app.file_selection = FileSelection::Multiple(vec![0]);
+        let _ = app.update(Message::PauseSelected);
+        assert_eq!(file(&app, second).state, FileState::Paused);
+        assert_eq!(app.queue_progress().0, 9);
+        assert_eq!(app.queue_progress().1, 11);
+
+        let command = app.update(Message::SuccessfulUpload(second));
+        run(&mut app, command).await;
+        assert_eq!(app.queue_progress().0, 0);
+        assert_eq!(app.queue_progress().1, 0);
+    }
+
+    #[tokio::test]
+    async fn failing_uploads_are_marked_failed() {
+        // Retries back off for seconds; let the clock skip ahead instead.
+        tokio::time::pause();
+
+        let dir = tempfile::tempdir().unwrap();
+        let api = FakeApi::new(Duration::from_millis(0), Some(1));
+        let mut app = app(api);
+
+        let id = add_file(&mut app, &dir, "clip.mp4", b"clip").await;
+        // The duplicate check failed too, so the file was never confirmed.
+        assert_eq!(file(&app, id).state, FileState::Analyzed);
+
+        upload_pending(&mut app).await;
+
+        let failed = file(&app, id);
+        assert_eq!(failed.state, FileState::Failed);
+        assert_eq!(
+            failed.error,
+            Some(ApiError::ServerError(
+                503,
+                Some("Injected failure".to_owned())
+            ))
+        );
+        assert!(failed.progress.is_none());
+    }
+}
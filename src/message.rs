@@ -1,6 +1,10 @@
-use crate::api::{Config, User};
+use crate::api::{ApiError, User};
+use crate::config::Profile;
 use crate::scenes::WelcomeMessage;
-use crate::widgets::file::{AnalyzeResult, FileMessage, FileState};
+use crate::widgets::file::{AnalyzeResult, FileMessage, FileState, SortColumn};
+use crate::widgets::presets::PresetMessage;
+use crate::widgets::tags::TagMessage;
+use crate::ScannedFile;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Filter {
@@ -22,8 +26,8 @@ impl Filter {
       ],
       Filter::Duplicate => vec![FileState::Duplicate],
       Filter::Completed => vec![FileState::Completed],
-      Filter::Failed => vec![FileState::Failed],
-      Filter::Queued => vec![FileState::Queued, FileState::Uploading],
+      Filter::Failed => vec![FileState::Failed, FileState::Cancelled, FileState::Invalid],
+      Filter::Queued => vec![FileState::Queued, FileState::Uploading, FileState::Paused],
     }
   }
 }
@@ -38,18 +42,38 @@ impl Default for Filter {
 #[derive(Debug, Clone)]
 pub enum Message {
   EventOccurred(iced_native::Event),
+  PathScanned(Vec<ScannedFile>),
   FileAnalyzed(u64, AnalyzeResult),
   FileMessage(u64, FileMessage),
   WelcomeMessage(WelcomeMessage),
-  SetConfigAndUser(Config, User),
+  Login(Profile),
+  LoggedIn(Profile, User),
+  ForgetProfile(String),
   DuplicateCheckResponse(Vec<String>, Vec<String>),
   SetFilter(Filter),
   SelectFile(usize),
-  SetTags(String),
+  SortBy(SortColumn),
+  TagMessage(TagMessage),
+  PresetMessage(PresetMessage),
+  SetSource(String),
+  SetDescription(String),
   Enqueue,
   StartUpload,
   SuccessfulUpload(u64),
-  FailedUpload(u64),
+  FailedUpload(u64, ApiError),
   BeginUploadBatch,
+  SetUploadConcurrency(usize),
+  PauseSelected,
+  ResumeSelected,
+  CancelSelected,
+  RemoveSelected,
+  RehashSelected,
+  PauseAll,
+  ResumeAll,
+  CancelAll,
+  /// Writes the session if it changed since it was last written.
+  SaveSession,
+  /// Writes the settings if they changed since they were last written.
+  SaveSettings,
   Noop,
 }
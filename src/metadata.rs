@@ -0,0 +1,876 @@
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::sniff::{self, VideoFormat};
+
+/// Largest box or element read into memory. Anything bigger is skipped, so a
+/// damaged header cannot make us allocate gigabytes.
+const MAX_READ_LENGTH: u64 = 16 * 1024 * 1024;
+
+// Matroska element IDs, with their length markers.
+const EBML_SEGMENT: u32 = 0x1853_8067;
+const EBML_INFO: u32 = 0x1549_a966;
+const EBML_TRACKS: u32 = 0x1654_ae6b;
+const EBML_CLUSTER: u32 = 0x1f43_b675;
+const EBML_SIMPLE_BLOCK: u32 = 0xa3;
+const EBML_BLOCK_GROUP: u32 = 0xa0;
+const EBML_TIMECODE_SCALE: u32 = 0x2a_d7b1;
+const EBML_DURATION: u32 = 0x4489;
+const EBML_TRACK_ENTRY: u32 = 0xae;
+const EBML_TRACK_TYPE: u32 = 0x83;
+const EBML_CODEC_ID: u32 = 0x86;
+const EBML_DEFAULT_DURATION: u32 = 0x23_e383;
+const EBML_VIDEO: u32 = 0xe0;
+const EBML_PIXEL_WIDTH: u32 = 0xb0;
+const EBML_PIXEL_HEIGHT: u32 = 0xba;
+
+/// Nanoseconds per timecode unit when a Matroska file does not say.
+const DEFAULT_TIMECODE_SCALE: u64 = 1_000_000;
+
+#[derive(Error, Debug)]
+pub enum MetadataError {
+  #[error(transparent)]
+  Io(#[from] io::Error),
+  #[error("{0}")]
+  Invalid(String),
+}
+
+fn invalid(reason: impl Into<String>) -> MetadataError {
+  MetadataError::Invalid(reason.into())
+}
+
+/// What the container says about the video. Every field is optional as
+/// files leave out or hide all sorts of things.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VideoMetadata {
+  pub duration: Option<Duration>,
+  pub width: Option<u32>,
+  pub height: Option<u32>,
+  pub frame_rate: Option<f64>,
+  pub video_codec: Option<String>,
+  pub audio_codec: Option<String>,
+}
+
+impl VideoMetadata {
+  pub fn bitrate(&self, size: u64) -> Option<u64> {
+    let seconds = self.duration?.as_secs_f64();
+
+    if seconds > 0.0 {
+      Some((size as f64 * 8.0 / seconds) as u64)
+    } else {
+      None
+    }
+  }
+
+  pub fn pixels(&self) -> Option<u64> {
+    Some(u64::from(self.width?) * u64::from(self.height?))
+  }
+}
+
+/// Reads the metadata of the MP4, QuickTime, Matroska or WebM file at
+/// `path`, checking along the way that its structure is complete. Other
+/// formats come back empty.
+pub fn read(path: &Path) -> Result<VideoMetadata, MetadataError> {
+  let length = fs::metadata(path)?.len();
+  let mut file = io::BufReader::new(fs::File::open(path)?);
+
+  match sniff::sniff(path)? {
+    Some(VideoFormat::Mp4) | Some(VideoFormat::QuickTime) => read_mp4(&mut file, length),
+    Some(VideoFormat::Matroska) | Some(VideoFormat::WebM) => read_matroska(&mut file, length),
+    _ => Ok(VideoMetadata::default()),
+  }
+}
+
+fn read_exact_vec(file: &mut impl Read, length: u64) -> io::Result<Vec<u8>> {
+  if length > MAX_READ_LENGTH {
+    return Err(io::Error::new(io::ErrorKind::InvalidData, "Box too large"));
+  }
+
+  let mut buffer = vec![0; length as usize];
+  file.read_exact(&mut buffer)?;
+  Ok(buffer)
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u16> {
+  Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+  Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(bytes: &[u8], at: usize) -> Option<u64> {
+  Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+#[derive(Debug, Default)]
+struct Mp4Track {
+  handler: Option<[u8; 4]>,
+  timescale: u32,
+  width: Option<u32>,
+  height: Option<u32>,
+  codec: Option<[u8; 4]>,
+  sample_count: u64,
+  /// Length of all samples together, in `timescale` units.
+  sample_duration: u64,
+}
+
+#[derive(Debug, Default)]
+struct Mp4 {
+  timescale: u32,
+  duration: u64,
+  tracks: Vec<Mp4Track>,
+  found_movie: bool,
+}
+
+fn read_mp4<R: Read + Seek>(file: &mut R, length: u64) -> Result<VideoMetadata, MetadataError> {
+  let mut mp4 = Mp4::default();
+  read_mp4_boxes(file, 0, length, &mut mp4)?;
+
+  if !mp4.found_movie {
+    return Err(invalid("No moov atom; the file was not completely written"));
+  }
+
+  let mut metadata = VideoMetadata::default();
+
+  if mp4.timescale > 0 {
+    metadata.duration = Some(Duration::from_secs_f64(
+      mp4.duration as f64 / f64::from(mp4.timescale),
+    ));
+  }
+
+  let video = mp4
+    .tracks
+    .iter()
+    .find(|track| track.handler == Some(*b"vide"));
+
+  if let Some(video) = video {
+    metadata.width = video.width;
+    metadata.height = video.height;
+    metadata.video_codec = video.codec.map(|codec| mp4_codec_name(&codec));
+
+    if video.sample_duration > 0 {
+      metadata.frame_rate =
+        Some(video.sample_count as f64 * f64::from(video.timescale) / video.sample_duration as f64);
+    }
+  }
+
+  metadata.audio_codec = mp4
+    .tracks
+    .iter()
+    .find(|track| track.handler == Some(*b"soun"))
+    .and_then(|track| track.codec)
+    .map(|codec| mp4_codec_name(&codec));
+
+  Ok(metadata)
+}
+
+/// Walks the boxes between `start` and `end`, descending into those that
+/// lead to the track descriptions and skipping everything else, including
+/// the media data. Fails if a box does not fit in its parent or the file.
+fn read_mp4_boxes<R: Read + Seek>(
+  file: &mut R,
+  start: u64,
+  end: u64,
+  mp4: &mut Mp4,
+) -> Result<(), MetadataError> {
+  let mut position = start;
+
+  while position + 8 <= end {
+    file.seek(SeekFrom::Start(position))?;
+
+    let mut header = [0; 8];
+    file.read_exact(&mut header)?;
+
+    let kind = [header[4], header[5], header[6], header[7]];
+    let (header_length, box_length) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+      0 => (8, end - position),
+      1 => {
+        let mut large_size = [0; 8];
+        file.read_exact(&mut large_size)?;
+        (16, u64::from_be_bytes(large_size))
+      }
+      size => (8, u64::from(size)),
+    };
+
+    if box_length < header_length {
+      return Err(invalid(format!("Broken box header at byte {}", position)));
+    }
+
+    // The size is untrusted; a corrupt 64-bit size must not wrap around
+    // and send us backwards.
+    let box_end = match position.checked_add(box_length) {
+      Some(box_end) if box_end > position => box_end,
+      _ => return Err(invalid(format!("Broken box header at byte {}", position))),
+    };
+
+    if box_end > end {
+      return Err(match &kind {
+        b"mdat" => invalid("The media data is cut off; the file is incomplete"),
+        _ => invalid(format!(
+          "The {} box is cut off; the file is incomplete",
+          String::from_utf8_lossy(&kind)
+        )),
+      });
+    }
+
+    let payload_start = position + header_length;
+    let payload_length = box_end - payload_start;
+
+    match &kind {
+      b"trak" => {
+        mp4.tracks.push(Mp4Track::default());
+        read_mp4_boxes(file, payload_start, box_end, mp4)?;
+      }
+      b"moov" => {
+        read_mp4_boxes(file, payload_start, box_end, mp4)?;
+        mp4.found_movie = true;
+      }
+      b"mdia" | b"minf" | b"stbl" => read_mp4_boxes(file, payload_start, box_end, mp4)?,
+      b"mvhd" | b"tkhd" | b"mdhd" | b"hdlr" | b"stsd" | b"stts" => {
+        if let Ok(payload) = read_exact_vec(file, payload_length) {
+          parse_mp4_box(&kind, &payload, mp4);
+        }
+      }
+      _ => {}
+    }
+
+    position = box_end;
+  }
+
+  Ok(())
+}
+
+fn mp4_timing(payload: &[u8]) -> Option<(u32, u64)> {
+  match payload.first()? {
+    1 => Some((be_u32(payload, 20)?, be_u64(payload, 24)?)),
+    _ => Some((be_u32(payload, 12)?, u64::from(be_u32(payload, 16)?))),
+  }
+}
+
+fn parse_mp4_box(kind: &[u8; 4], payload: &[u8], mp4: &mut Mp4) {
+  if kind == b"mvhd" {
+    if let Some((timescale, duration)) = mp4_timing(payload) {
+      mp4.timescale = timescale;
+      mp4.duration = duration;
+    }
+    return;
+  }
+
+  let track = match mp4.tracks.last_mut() {
+    Some(track) => track,
+    None => return,
+  };
+
+  match kind {
+    b"tkhd" => {
+      // Display size as 16.16 fixed point, after the transformation matrix.
+      let at = if payload.first() == Some(&1) { 88 } else { 76 };
+
+      if let (Some(width), Some(height)) = (be_u32(payload, at), be_u32(payload, at + 4)) {
+        if width > 0 && height > 0 {
+          track.width = Some(width >> 16);
+          track.height = Some(height >> 16);
+        }
+      }
+    }
+    b"mdhd" => {
+      if let Some((timescale, _)) = mp4_timing(payload) {
+        track.timescale = timescale;
+      }
+    }
+    b"hdlr" => {
+      track.handler = payload
+        .get(8..12)
+        .and_then(|handler| handler.try_into().ok());
+    }
+    b"stsd" => {
+      // The first sample entry names the codec; video entries also carry
+      // the coded size, used when the track header has none.
+      track.codec = payload.get(12..16).and_then(|codec| codec.try_into().ok());
+
+      if track.width.is_none() && track.handler == Some(*b"vide") {
+        track.width = be_u16(payload, 40).map(u32::from);
+        track.height = be_u16(payload, 42).map(u32::from);
+      }
+    }
+    b"stts" => {
+      let entries = be_u32(payload, 4).unwrap_or_default() as usize;
+
+      for entry in 0..entries {
+        let at = 8 + entry * 8;
+
+        match (be_u32(payload, at), be_u32(payload, at + 4)) {
+          (Some(count), Some(delta)) => {
+            track.sample_count += u64::from(count);
+            track.sample_duration += u64::from(count) * u64::from(delta);
+          }
+          _ => break,
+        }
+      }
+    }
+    _ => {}
+  }
+}
+
+fn mp4_codec_name(codec: &[u8; 4]) -> String {
+  let name = match codec {
+    b"avc1" | b"avc3" => "H.264",
+    b"hvc1" | b"hev1" => "H.265",
+    b"av01" => "AV1",
+    b"vp08" => "VP8",
+    b"vp09" => "VP9",
+    b"mp4v" => "MPEG-4",
+    b"apcn" | b"apch" | b"apcs" | b"apco" | b"ap4h" => "ProRes",
+    b"mp4a" => "AAC",
+    b"Opus" => "Opus",
+    b"ac-3" => "AC-3",
+    b"ec-3" => "E-AC-3",
+    b"fLaC" => "FLAC",
+    b".mp3" => "MP3",
+    b"alac" => "ALAC",
+    b"lpcm" | b"sowt" | b"twos" => "PCM",
+    _ => return String::from_utf8_lossy(codec).trim().to_owned(),
+  };
+
+  name.to_owned()
+}
+
+/// A Matroska element: its ID with the length marker kept, as IDs are
+/// written in the spec, and its data.
+struct Element<'a> {
+  id: u32,
+  data: &'a [u8],
+}
+
+/// Length of a variable-length integer, from the position of the first set
+/// bit of its first byte.
+fn vint_length(first: u8) -> Option<usize> {
+  match first.leading_zeros() {
+    zeros if zeros < 8 => Some(zeros as usize + 1),
+    _ => None,
+  }
+}
+
+/// Decodes an element size. `None` means the size is unknown, which live
+/// streams use for elements that run to the end of their parent.
+fn vint_size(bytes: &[u8]) -> Option<u64> {
+  let length = bytes.len();
+  let mut value = u64::from(bytes[0] & 0xff_u8.checked_shr(length as u32).unwrap_or(0));
+
+  for byte in &bytes[1..] {
+    value = value << 8 | u64::from(*byte);
+  }
+
+  if value == (1 << (7 * length)) - 1 {
+    None
+  } else {
+    Some(value)
+  }
+}
+
+fn ebml_elements(mut data: &[u8]) -> Vec<Element<'_>> {
+  let mut elements = Vec::new();
+
+  while let Some(&first) = data.first() {
+    let id_length = match vint_length(first) {
+      Some(length) if length <= 4 && length < data.len() => length,
+      _ => break,
+    };
+    let size_length = match vint_length(data[id_length]) {
+      Some(length) if id_length + length <= data.len() => length,
+      _ => break,
+    };
+
+    let id = data[..id_length]
+      .iter()
+      .fold(0, |id, byte| id << 8 | u32::from(*byte));
+    let header_length = id_length + size_length;
+    let rest = &data[header_length..];
+    let size = vint_size(&data[id_length..header_length])
+      .map(|size| (size as usize).min(rest.len()))
+      .unwrap_or_else(|| rest.len());
+
+    elements.push(Element {
+      id,
+      data: &rest[..size],
+    });
+    data = &rest[size..];
+  }
+
+  elements
+}
+
+fn ebml_uint(data: &[u8]) -> u64 {
+  data
+    .iter()
+    .take(8)
+    .fold(0, |value, byte| value << 8 | u64::from(*byte))
+}
+
+fn ebml_float(data: &[u8]) -> Option<f64> {
+  match data.len() {
+    4 => Some(f64::from(f32::from_be_bytes(data.try_into().ok()?))),
+    8 => Some(f64::from_be_bytes(data.try_into().ok()?)),
+    _ => None,
+  }
+}
+
+fn read_ebml_header(
+  file: &mut (impl Read + Seek),
+  position: u64,
+) -> Result<(u32, Option<u64>, u64), MetadataError> {
+  let broken = || invalid(format!("Broken EBML element at byte {}", position));
+  let cut_off = |err: io::Error| match err.kind() {
+    io::ErrorKind::UnexpectedEof => invalid("The file ends in the middle of an element"),
+    _ => MetadataError::Io(err),
+  };
+
+  file.seek(SeekFrom::Start(position))?;
+
+  let mut first = [0; 1];
+  file.read_exact(&mut first).map_err(cut_off)?;
+
+  let id_length = match vint_length(first[0]) {
+    Some(length) if length <= 4 => length,
+    _ => return Err(broken()),
+  };
+  let mut id_bytes = vec![first[0]; id_length];
+  file.read_exact(&mut id_bytes[1..]).map_err(cut_off)?;
+
+  file.read_exact(&mut first).map_err(cut_off)?;
+  let size_length = match vint_length(first[0]) {
+    Some(length) => length,
+    None => return Err(broken()),
+  };
+  let mut size_bytes = vec![first[0]; size_length];
+  file.read_exact(&mut size_bytes[1..]).map_err(cut_off)?;
+
+  let id = id_bytes
+    .iter()
+    .fold(0, |id, byte| id << 8 | u32::from(*byte));
+
+  Ok((id, vint_size(&size_bytes), (id_length + size_length) as u64))
+}
+
+/// Walks the top-level elements and those of the segment, reading Info and
+/// Tracks and skipping over everything else by its size to check that the
+/// file is complete.
+fn read_matroska<R: Read + Seek>(
+  file: &mut R,
+  length: u64,
+) -> Result<VideoMetadata, MetadataError> {
+  let mut metadata = VideoMetadata::default();
+  let mut position = 0;
+  let mut found_tracks = false;
+
+  while position < length {
+    let (id, size, header_length) = read_ebml_header(file, position)?;
+    let data_start = position + header_length;
+
+    let size = match size {
+      Some(size) => size,
+      // Live recordings leave the size of the segment and its clusters
+      // open; their contents follow directly.
+      None => {
+        position = data_start;
+        continue;
+      }
+    };
+
+    let data_end = match data_start.checked_add(size) {
+      Some(data_end) if data_end <= length => data_end,
+      _ => {
+        return Err(match id {
+          EBML_SEGMENT | EBML_CLUSTER | EBML_SIMPLE_BLOCK | EBML_BLOCK_GROUP => {
+            invalid("The media data is cut off; the file is incomplete")
+          }
+          _ => invalid(format!(
+            "The element at byte {} is cut off; the file is incomplete",
+            position
+          )),
+        })
+      }
+    };
+
+    match id {
+      // Step into the segment instead of over it.
+      EBML_SEGMENT => {
+        position = data_start;
+        continue;
+      }
+      EBML_INFO => {
+        if let Ok(data) = read_exact_vec(file, size) {
+          parse_matroska_info(&data, &mut metadata);
+        }
+      }
+      EBML_TRACKS => {
+        if let Ok(data) = read_exact_vec(file, size) {
+          parse_matroska_tracks(&data, &mut metadata);
+          found_tracks = true;
+        }
+      }
+      _ => {}
+    }
+
+    position = data_end;
+  }
+
+  if !found_tracks {
+    return Err(invalid("No track list; the file is incomplete"));
+  }
+
+  Ok(metadata)
+}
+
+fn parse_matroska_info(data: &[u8], metadata: &mut VideoMetadata) {
+  let elements = ebml_elements(data);
+  let timecode_scale = elements
+    .iter()
+    .find(|element| element.id == EBML_TIMECODE_SCALE)
+    .map(|element| ebml_uint(element.data))
+    .unwrap_or(DEFAULT_TIMECODE_SCALE);
+
+  metadata.duration = elements
+    .iter()
+    .find(|element| element.id == EBML_DURATION)
+    .and_then(|element| ebml_float(element.data))
+    .filter(|duration| duration.is_finite() && *duration >= 0.0)
+    .map(|duration| Duration::from_nanos((duration * timecode_scale as f64) as u64));
+}
+
+fn parse_matroska_tracks(data: &[u8], metadata: &mut VideoMetadata) {
+  let entries = ebml_elements(data)
+    .into_iter()
+    .filter(|element| element.id == EBML_TRACK_ENTRY);
+
+  for entry in entries {
+    let elements = ebml_elements(entry.data);
+    let find = |id| elements.iter().find(|element| element.id == id);
+
+    let codec = find(EBML_CODEC_ID)
+      .map(|element| matroska_codec_name(&String::from_utf8_lossy(element.data)));
+
+    match find(EBML_TRACK_TYPE).map(|element| ebml_uint(element.data)) {
+      Some(1) if metadata.video_codec.is_none() => {
+        metadata.video_codec = codec;
+        metadata.frame_rate = find(EBML_DEFAULT_DURATION)
+          .map(|element| ebml_uint(element.data))
+          .filter(|nanos| *nanos > 0)
+          .map(|nanos| 1e9 / nanos as f64);
+
+        if let Some(video) = find(EBML_VIDEO) {
+          for element in ebml_elements(video.data) {
+            match element.id {
+              EBML_PIXEL_WIDTH => metadata.width = Some(ebml_uint(element.data) as u32),
+              EBML_PIXEL_HEIGHT => metadata.height = Some(ebml_uint(element.data) as u32),
+              _ => {}
+            }
+          }
+        }
+      }
+      Some(2) if metadata.audio_codec.is_none() => metadata.audio_codec = codec,
+      _ => {}
+    }
+  }
+}
+
+fn matroska_codec_name(codec_id: &str) -> String {
+  let codec_id = codec_id.trim_end_matches('\0');
+
+  let name = match codec_id {
+    "V_MPEG4/ISO/AVC" => "H.264",
+    "V_MPEGH/ISO/HEVC" => "H.265",
+    "V_AV1" => "AV1",
+    "V_VP8" => "VP8",
+    "V_VP9" => "VP9",
+    "V_THEORA" => "Theora",
+    "A_OPUS" => "Opus",
+    "A_VORBIS" => "Vorbis",
+    "A_AC3" => "AC-3",
+    "A_EAC3" => "E-AC-3",
+    "A_FLAC" => "FLAC",
+    "A_MPEG/L3" => "MP3",
+    id if id.starts_with("A_AAC") => "AAC",
+    id if id.starts_with("A_PCM") => "PCM",
+    id => id,
+  };
+
+  name.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+
+  fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut bytes = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    bytes.extend_from_slice(kind);
+    bytes.extend_from_slice(payload);
+    bytes
+  }
+
+  fn full_box(kind: &[u8; 4], fields: &[(usize, &[u8])], length: usize) -> Vec<u8> {
+    let mut payload = vec![0; length];
+
+    for (at, bytes) in fields {
+      payload[*at..*at + bytes.len()].copy_from_slice(bytes);
+    }
+
+    mp4_box(kind, &payload)
+  }
+
+  fn video_track() -> Vec<u8> {
+    let tkhd = full_box(
+      b"tkhd",
+      &[
+        (76, &(1280u32 << 16).to_be_bytes()),
+        (80, &(720u32 << 16).to_be_bytes()),
+      ],
+      84,
+    );
+    let mdhd = full_box(b"mdhd", &[(12, &30_000u32.to_be_bytes())], 24);
+    let hdlr = full_box(b"hdlr", &[(8, b"vide")], 24);
+    let stsd = full_box(b"stsd", &[(12, b"avc1")], 44);
+    let stts = full_box(
+      b"stts",
+      &[
+        (4, &1u32.to_be_bytes()),
+        (8, &300u32.to_be_bytes()),
+        (12, &1001u32.to_be_bytes()),
+      ],
+      16,
+    );
+
+    let stbl = mp4_box(b"stbl", &[stsd, stts].concat());
+    let minf = mp4_box(b"minf", &stbl);
+    let mdia = mp4_box(b"mdia", &[mdhd, hdlr, minf].concat());
+    mp4_box(b"trak", &[tkhd, mdia].concat())
+  }
+
+  fn movie() -> Vec<u8> {
+    let mvhd = full_box(
+      b"mvhd",
+      &[(12, &1000u32.to_be_bytes()), (16, &10_010u32.to_be_bytes())],
+      100,
+    );
+    mp4_box(b"moov", &[mvhd, video_track()].concat())
+  }
+
+  fn read_mp4_bytes(bytes: &[u8]) -> Result<VideoMetadata, MetadataError> {
+    read_mp4(&mut Cursor::new(bytes), bytes.len() as u64)
+  }
+
+  fn reason(result: Result<VideoMetadata, MetadataError>) -> String {
+    match result {
+      Err(MetadataError::Invalid(reason)) => reason,
+      other => panic!("expected an invalid file, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn reads_mp4_tracks() {
+    let ftyp = mp4_box(b"ftyp", b"isom\0\0\0\0");
+    let mdat = mp4_box(b"mdat", &[0; 64]);
+    let metadata = read_mp4_bytes(&[ftyp, movie(), mdat].concat()).unwrap();
+
+    assert_eq!(metadata.duration, Some(Duration::from_millis(10_010)));
+    assert_eq!(metadata.width, Some(1280));
+    assert_eq!(metadata.height, Some(720));
+    assert_eq!(metadata.video_codec.as_deref(), Some("H.264"));
+    assert_eq!(metadata.audio_codec, None);
+
+    let frame_rate = metadata.frame_rate.unwrap();
+    assert!((frame_rate - 29.97).abs() < 0.01, "{}", frame_rate);
+  }
+
+  #[test]
+  fn mp4_without_moov_is_invalid() {
+    let bytes = [
+      mp4_box(b"ftyp", b"isom\0\0\0\0"),
+      mp4_box(b"mdat", &[0; 64]),
+    ]
+    .concat();
+
+    assert_eq!(
+      reason(read_mp4_bytes(&bytes)),
+      "No moov atom; the file was not completely written"
+    );
+  }
+
+  #[test]
+  fn truncated_mp4_is_invalid() {
+    let bytes = [
+      mp4_box(b"ftyp", b"isom\0\0\0\0"),
+      movie(),
+      mp4_box(b"mdat", &[0; 64]),
+    ]
+    .concat();
+
+    assert_eq!(
+      reason(read_mp4_bytes(&bytes[..bytes.len() - 10])),
+      "The media data is cut off; the file is incomplete"
+    );
+
+    let moov_end = 16 + movie().len();
+    assert_eq!(
+      reason(read_mp4_bytes(&bytes[..moov_end - 4])),
+      "The moov box is cut off; the file is incomplete"
+    );
+  }
+
+  #[test]
+  fn overflowing_mp4_box_size_is_invalid() {
+    let mut bytes = mp4_box(b"ftyp", b"isom\0\0\0\0");
+    bytes.extend_from_slice(&1u32.to_be_bytes());
+    bytes.extend_from_slice(b"free");
+    bytes.extend_from_slice(&u64::MAX.to_be_bytes());
+    bytes.extend_from_slice(&[0; 16]);
+
+    assert_eq!(
+      reason(read_mp4_bytes(&bytes)),
+      "Broken box header at byte 16"
+    );
+  }
+
+  #[test]
+  fn decodes_vint_sizes() {
+    assert_eq!(vint_length(0x81), Some(1));
+    assert_eq!(vint_length(0x40), Some(2));
+    assert_eq!(vint_length(0x01), Some(8));
+    assert_eq!(vint_length(0x00), None);
+
+    assert_eq!(vint_size(&[0x85]), Some(5));
+    assert_eq!(vint_size(&[0x40, 0x02]), Some(2));
+    assert_eq!(vint_size(&[0x01, 0, 0, 0, 0, 0, 0x01, 0x00]), Some(256));
+    // All value bits set means the size is unknown.
+    assert_eq!(vint_size(&[0xff]), None);
+    assert_eq!(
+      vint_size(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+      None
+    );
+  }
+
+  fn ebml(id: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.push(0x80 | data.len() as u8);
+    bytes.extend_from_slice(data);
+    bytes
+  }
+
+  #[test]
+  fn splits_ebml_elements() {
+    let bytes = [ebml(&[0x83], &[1]), ebml(&[0x86], b"V_VP9")].concat();
+    let elements = ebml_elements(&bytes);
+
+    assert_eq!(elements.len(), 2);
+    assert_eq!(elements[0].id, EBML_TRACK_TYPE);
+    assert_eq!(elements[0].data, &[1]);
+    assert_eq!(elements[1].id, EBML_CODEC_ID);
+    assert_eq!(elements[1].data, b"V_VP9");
+
+    // A size running past the end is cut to what is there.
+    let elements = ebml_elements(&[0x86, 0x88, b'V', b'_']);
+    assert_eq!(elements.len(), 1);
+    assert_eq!(elements[0].data, b"V_");
+
+    assert!(ebml_elements(&[0x00, 0x81, 0x00]).is_empty());
+  }
+
+  fn webm() -> Vec<u8> {
+    let header = ebml(&[0x1a, 0x45, 0xdf, 0xa3], &ebml(&[0x42, 0x82], b"webm"));
+    let info = ebml(
+      &[0x15, 0x49, 0xa9, 0x66],
+      &[
+        ebml(&[0x2a, 0xd7, 0xb1], &[0x0f, 0x42, 0x40]),
+        ebml(&[0x44, 0x89], &2500.0f64.to_be_bytes()),
+      ]
+      .concat(),
+    );
+    let video = ebml(
+      &[0xe0],
+      &[ebml(&[0xb0], &[0x02, 0x80]), ebml(&[0xba], &[0x01, 0xe0])].concat(),
+    );
+    let track = ebml(
+      &[0xae],
+      &[
+        ebml(&[0x83], &[1]),
+        ebml(&[0x86], b"V_VP9"),
+        ebml(&[0x23, 0xe3, 0x83], &40_000_000u32.to_be_bytes()),
+        video,
+      ]
+      .concat(),
+    );
+    let audio = ebml(
+      &[0xae],
+      &[ebml(&[0x83], &[2]), ebml(&[0x86], b"A_OPUS")].concat(),
+    );
+    let tracks = ebml(&[0x16, 0x54, 0xae, 0x6b], &[track, audio].concat());
+    let cluster = ebml(
+      &[0x1f, 0x43, 0xb6, 0x75],
+      &[
+        ebml(&[0xe7], &[0]),
+        ebml(&[0xa3], &[0x81, 0, 0, 0x80, 1, 2, 3]),
+      ]
+      .concat(),
+    );
+    let segment = ebml(&[0x18, 0x53, 0x80, 0x67], &[info, tracks, cluster].concat());
+
+    [header, segment].concat()
+  }
+
+  fn read_matroska_bytes(bytes: &[u8]) -> Result<VideoMetadata, MetadataError> {
+    read_matroska(&mut Cursor::new(bytes), bytes.len() as u64)
+  }
+
+  #[test]
+  fn reads_webm_tracks() {
+    let metadata = read_matroska_bytes(&webm()).unwrap();
+
+    assert_eq!(metadata.duration, Some(Duration::from_millis(2500)));
+    assert_eq!(metadata.width, Some(640));
+    assert_eq!(metadata.height, Some(480));
+    assert_eq!(metadata.frame_rate, Some(25.0));
+    assert_eq!(metadata.video_codec.as_deref(), Some("VP9"));
+    assert_eq!(metadata.audio_codec.as_deref(), Some("Opus"));
+  }
+
+  #[test]
+  fn truncated_webm_is_invalid() {
+    let bytes = webm();
+
+    assert_eq!(
+      reason(read_matroska_bytes(&bytes[..bytes.len() - 3])),
+      "The media data is cut off; the file is incomplete"
+    );
+  }
+
+  #[test]
+  fn webm_without_tracks_is_invalid() {
+    let header = ebml(&[0x1a, 0x45, 0xdf, 0xa3], &ebml(&[0x42, 0x82], b"webm"));
+    let segment = ebml(&[0x18, 0x53, 0x80, 0x67], &ebml(&[0xec], &[0; 4]));
+
+    assert_eq!(
+      reason(read_matroska_bytes(&[header, segment].concat())),
+      "No track list; the file is incomplete"
+    );
+  }
+
+  #[test]
+  fn broken_ebml_element_is_invalid() {
+    let header = ebml(&[0x1a, 0x45, 0xdf, 0xa3], &ebml(&[0x42, 0x82], b"webm"));
+    let bytes = [header.clone(), vec![0x00, 0x81, 0x00]].concat();
+
+    assert_eq!(
+      reason(read_matroska_bytes(&bytes)),
+      format!("Broken EBML element at byte {}", header.len())
+    );
+  }
+}
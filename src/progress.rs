@@ -0,0 +1,22 @@
+use std::time::{Duration, Instant};
+
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Passes progress on to `report` at most once every [`PROGRESS_INTERVAL`],
+/// so hashing or uploading a file does not flood the UI with messages.
+pub fn throttled(mut report: impl FnMut(u64)) -> impl FnMut(u64) {
+  let mut last_report: Option<Instant> = None;
+
+  move |bytes| {
+    let now = Instant::now();
+    let due = match last_report {
+      Some(last) => now.duration_since(last) >= PROGRESS_INTERVAL,
+      None => true,
+    };
+
+    if due {
+      last_report = Some(now);
+      report(bytes);
+    }
+  }
+}
@@ -0,0 +1,263 @@
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::tags::{self, Tag};
+use crate::widgets::file::File;
+
+/// How a rule matches a file's full path. Paths always use `/` as the
+/// separator, also on Windows.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PathPattern {
+  /// Has to match the whole path, e.g. `**/Spinfest/**`.
+  Glob(String),
+  /// Can match anywhere in the path. Named groups can be used in the tags,
+  /// source and description, e.g. `/(?P<event>[^/]+)/\d{4}/` with the tag
+  /// `$event`.
+  Regex(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Rule {
+  pub name: String,
+  #[serde(flatten)]
+  pub pattern: PathPattern,
+  /// Space-separated, in the archive's format.
+  #[serde(default)]
+  pub tags: String,
+  #[serde(default)]
+  pub source: Option<String>,
+  #[serde(default)]
+  pub description: Option<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum RuleError {
+  #[error("Rule \"{0}\" has an invalid glob: {1}")]
+  Glob(String, glob::PatternError),
+  #[error("Rule \"{0}\" has an invalid regex: {1}")]
+  Regex(String, regex::Error),
+}
+
+#[derive(Debug)]
+enum Matcher {
+  Glob(Pattern),
+  Regex(Regex),
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+  rule: Rule,
+  matcher: Matcher,
+}
+
+#[derive(Debug, Default)]
+pub struct RuleSet {
+  rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+  pub fn compile(rules: &[Rule]) -> (Self, Vec<RuleError>) {
+    let mut set = Self::default();
+    let mut errors = Vec::new();
+
+    for rule in rules {
+      let matcher = match &rule.pattern {
+        PathPattern::Glob(glob) => Pattern::new(glob)
+          .map(Matcher::Glob)
+          .map_err(|err| RuleError::Glob(rule.name.clone(), err)),
+        PathPattern::Regex(regex) => Regex::new(regex)
+          .map(Matcher::Regex)
+          .map_err(|err| RuleError::Regex(rule.name.clone(), err)),
+      };
+
+      match matcher {
+        Ok(matcher) => set.rules.push(CompiledRule {
+          rule: rule.clone(),
+          matcher,
+        }),
+        Err(err) => errors.push(err),
+      }
+    }
+
+    (set, errors)
+  }
+
+  /// Applies every rule matching the file's path, in order. Tags add up,
+  /// while a later rule's source or description replaces an earlier one.
+  pub fn apply(&self, file: &mut File) {
+    let path = file.path.to_string_lossy().replace('\\', "/");
+
+    for compiled in &self.rules {
+      let rule = &compiled.rule;
+
+      // Turns `$name` into what the named group matched, if there is one.
+      let expand: Box<dyn Fn(&str) -> String> = match &compiled.matcher {
+        Matcher::Glob(pattern) => {
+          let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+          };
+
+          if !pattern.matches_with(&path, options) {
+            continue;
+          }
+
+          Box::new(str::to_owned)
+        }
+        Matcher::Regex(regex) => match regex.captures(&path) {
+          Some(captures) => Box::new(move |template: &str| {
+            let mut expanded = String::new();
+            captures.expand(template, &mut expanded);
+            expanded
+          }),
+          None => continue,
+        },
+      };
+
+      for template in rule.tags.split_whitespace() {
+        // Each tag is expanded on its own so a folder like `Jane Doe`
+        // becomes the single tag `jane_doe`.
+        match Tag::parse(&expand(template)) {
+          Ok(tag) => tags::add(&mut file.tags, tag),
+          Err(err) => eprintln!("Ignoring tag of rule \"{}\": {}", rule.name, err),
+        }
+      }
+
+      if let Some(source) = &rule.source {
+        file.source = expand(source);
+      }
+
+      if let Some(description) = &rule.description {
+        file.description = expand(description);
+      }
+
+      file.rules.push(rule.name.clone());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use super::*;
+
+  fn rule(name: &str, pattern: PathPattern) -> Rule {
+    Rule {
+      name: name.to_owned(),
+      pattern,
+      tags: String::new(),
+      source: None,
+      description: None,
+    }
+  }
+
+  fn apply(rules: &[Rule], path: &str) -> File {
+    let (set, errors) = RuleSet::compile(rules);
+    assert!(errors.is_empty());
+
+    let mut file = File {
+      path: PathBuf::from(path),
+      ..Default::default()
+    };
+    set.apply(&mut file);
+    file
+  }
+
+  fn tag_names(file: &File) -> Vec<&str> {
+    file.tags.iter().map(Tag::as_str).collect()
+  }
+
+  #[test]
+  fn globs_match_whole_absolute_paths() {
+    let rules = vec![Rule {
+      tags: "spinfest".to_owned(),
+      ..rule("spinfest", PathPattern::Glob("**/Spinfest/**".to_owned()))
+    }];
+
+    let file = apply(&rules, "/home/jane/Videos/Spinfest/2019/clip.mp4");
+    assert_eq!(tag_names(&file), vec!["spinfest"]);
+    assert_eq!(file.rules, vec!["spinfest"]);
+
+    // Windows paths are matched with `/` separators.
+    let file = apply(&rules, "C:\\Videos\\Spinfest\\clip.mp4");
+    assert_eq!(tag_names(&file), vec!["spinfest"]);
+
+    let file = apply(&rules, "/home/jane/Videos/Spinfest.mp4");
+    assert!(file.tags.is_empty());
+    assert!(file.rules.is_empty());
+
+    // `*` does not cross directories.
+    let rules = vec![Rule {
+      tags: "top".to_owned(),
+      ..rule("top", PathPattern::Glob("/videos/*.mp4".to_owned()))
+    }];
+    assert_eq!(tag_names(&apply(&rules, "/videos/clip.mp4")), vec!["top"]);
+    assert!(apply(&rules, "/videos/2019/clip.mp4").tags.is_empty());
+  }
+
+  #[test]
+  fn regex_groups_expand_into_single_tags() {
+    let rules = vec![Rule {
+      tags: "$performer event_$year".to_owned(),
+      source: Some("$year, by $performer".to_owned()),
+      ..rule(
+        "performer",
+        PathPattern::Regex(r"/(?P<performer>[^/]+)/(?P<year>\d{4})/".to_owned()),
+      )
+    }];
+
+    let file = apply(&rules, "/videos/Jane Doe/2019/clip.mp4");
+    assert_eq!(tag_names(&file), vec!["jane_doe", "event_2019"]);
+    assert_eq!(file.source, "2019, by Jane Doe");
+
+    assert!(apply(&rules, "/videos/clip.mp4").tags.is_empty());
+  }
+
+  #[test]
+  fn later_rules_override_source_and_description() {
+    let rules = vec![
+      Rule {
+        tags: "spin".to_owned(),
+        source: Some("first".to_owned()),
+        description: Some("first".to_owned()),
+        ..rule("first", PathPattern::Glob("**".to_owned()))
+      },
+      Rule {
+        tags: "spin event".to_owned(),
+        source: Some("second".to_owned()),
+        ..rule("second", PathPattern::Regex("clip".to_owned()))
+      },
+      Rule {
+        source: Some("unmatched".to_owned()),
+        ..rule("unmatched", PathPattern::Regex("other".to_owned()))
+      },
+    ];
+
+    let file = apply(&rules, "/videos/clip.mp4");
+    assert_eq!(tag_names(&file), vec!["spin", "event"]);
+    assert_eq!(file.source, "second");
+    // Rules without a description leave the earlier one.
+    assert_eq!(file.description, "first");
+    assert_eq!(file.rules, vec!["first", "second"]);
+  }
+
+  #[test]
+  fn invalid_patterns_are_reported() {
+    let rules = vec![
+      rule("glob", PathPattern::Glob("**/[".to_owned())),
+      rule("regex", PathPattern::Regex("(unclosed".to_owned())),
+      rule("valid", PathPattern::Glob("**".to_owned())),
+    ];
+
+    let (set, errors) = RuleSet::compile(&rules);
+
+    assert_eq!(set.rules.len(), 1);
+    assert!(matches!(&errors[0], RuleError::Glob(name, _) if name == "glob"));
+    assert!(matches!(&errors[1], RuleError::Regex(name, _) if name == "regex"));
+    assert_eq!(errors.len(), 2);
+  }
+}
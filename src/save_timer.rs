@@ -0,0 +1,36 @@
+use std::hash::Hash;
+use std::time::Duration;
+
+use futures::stream::{self, BoxStream};
+use iced_native::subscription::Recipe;
+use tokio::time::delay_for;
+
+use crate::message::Message;
+
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Sends its message once [`SAVE_DELAY`] has passed, so a burst of changes,
+/// like a folder being hashed or the window being resized, is written once
+/// instead of on every change.
+pub struct SaveTimer(pub Message);
+
+impl<H, I> Recipe<H, I> for SaveTimer
+where
+  H: std::hash::Hasher,
+{
+  type Output = Message;
+
+  fn hash(&self, state: &mut H) {
+    std::any::TypeId::of::<Self>().hash(state);
+    std::mem::discriminant(&self.0).hash(state);
+  }
+
+  fn stream(self: Box<Self>, _input: BoxStream<'static, I>) -> BoxStream<'static, Message> {
+    let message = self.0;
+
+    Box::pin(stream::once(async move {
+      delay_for(SAVE_DELAY).await;
+      message
+    }))
+  }
+}
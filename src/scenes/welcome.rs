@@ -1,52 +1,106 @@
 use iced::{
-  button, text_input, Align, Button, Color, Column, Command, Container, Element, Length, Text,
+  button, text_input, Align, Button, Color, Column, Command, Container, Element, Length, Row, Text,
   TextInput,
 };
 
-use crate::api;
+use crate::config::{self, Profile, Profiles};
 use crate::message::Message;
 use crate::styles;
 
 #[derive(Debug, Default)]
 pub struct WelcomeScene {
+  profiles: Vec<Profile>,
+  profile_buttons: Vec<button::State>,
+  profile_name: String,
+  profile_name_input: text_input::State,
+  host: String,
+  host_input: text_input::State,
   api_key: String,
   api_key_input: text_input::State,
   next_button: button::State,
+  forget_button: button::State,
   error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum WelcomeMessage {
+  ProfileSelected(usize),
+  ProfileNameInputChanged(String),
+  HostInputChanged(String),
   ApiKeyInputChanged(String),
   SetDisplayError(String),
+  ForgetProfile,
   NextScene,
 }
 
 impl WelcomeScene {
+  pub fn new(profiles: &Profiles) -> Self {
+    let mut scene = Self {
+      profiles: profiles.profiles.clone(),
+      profile_buttons: profiles
+        .profiles
+        .iter()
+        .map(|_| button::State::default())
+        .collect(),
+      profile_name: "spin-archive.org".to_owned(),
+      host: config::DEFAULT_HOST.to_owned(),
+      ..Default::default()
+    };
+
+    if let Some(profile) = profiles.active_profile() {
+      scene.fill(profile.clone());
+    }
+
+    scene
+  }
+
+  fn fill(&mut self, profile: Profile) {
+    self.profile_name = profile.name;
+    self.host = profile.host;
+    self.api_key = profile.api_key;
+    self.error = None;
+  }
+
   pub fn update(&mut self, message: WelcomeMessage) -> Command<Message> {
     match message {
+      WelcomeMessage::ProfileSelected(idx) => {
+        if let Some(profile) = self.profiles.get(idx).cloned() {
+          self.fill(profile);
+        }
+      }
+      WelcomeMessage::ProfileNameInputChanged(new_value) => {
+        self.profile_name = new_value;
+        self.error = None;
+      }
+      WelcomeMessage::HostInputChanged(new_value) => {
+        self.host = new_value;
+        self.error = None;
+      }
       WelcomeMessage::ApiKeyInputChanged(new_value) => {
         self.api_key = new_value;
         self.error = None;
       }
       WelcomeMessage::SetDisplayError(error) => self.error = Some(error),
+      WelcomeMessage::ForgetProfile => {
+        let name = self.profile_name.trim().to_owned();
+
+        if let Some(idx) = self.profiles.iter().position(|p| p.name == name) {
+          self.profiles.remove(idx);
+          self.profile_buttons.remove(idx);
+        }
+
+        self.api_key.clear();
+
+        return Command::perform(async move { name }, Message::ForgetProfile);
+      }
       WelcomeMessage::NextScene => {
-        let api_key = self.api_key.clone();
-        let config = api::Config::new(api_key.clone());
-
-        let cmd = Command::perform(
-          async move {
-            let config = api::Config::new(api_key);
-            let response = api::User::get(&config).await;
-            response
-          },
-          move |resp| match resp {
-            Ok(user) => Message::SetConfigAndUser(config.clone(), user),
-            Err(e) => Message::WelcomeMessage(WelcomeMessage::SetDisplayError(e.to_string())),
-          },
-        );
-
-        return cmd;
+        let profile = Profile {
+          name: self.profile_name.trim().to_owned(),
+          host: self.host.trim().to_owned(),
+          api_key: self.api_key.trim().to_owned(),
+        };
+
+        return Command::perform(async move { profile }, Message::Login);
       }
     };
 
@@ -54,8 +108,56 @@ impl WelcomeScene {
   }
 
   pub fn view(&mut self) -> Element<WelcomeMessage> {
+    let saved = self
+      .profiles
+      .iter()
+      .any(|profile| profile.name == self.profile_name.trim());
+
     let mut welcome = Column::new()
       .push(Text::new("spin-archive.org - MX").color(Color::WHITE))
+      .max_width(300)
+      .spacing(12)
+      .align_items(Align::Center);
+
+    if !self.profiles.is_empty() {
+      let profiles = self
+        .profiles
+        .iter()
+        .zip(self.profile_buttons.iter_mut())
+        .enumerate()
+        .fold(Row::new().spacing(6), |row, (idx, (profile, state))| {
+          row.push(
+            Button::new(state, styles::text(&profile.name))
+              .style(styles::Button::Transparent)
+              .padding(4)
+              .on_press(WelcomeMessage::ProfileSelected(idx)),
+          )
+        });
+
+      welcome = welcome.push(profiles);
+    }
+
+    welcome = welcome
+      .push(
+        TextInput::new(
+          &mut self.profile_name_input,
+          "Profile name",
+          &self.profile_name,
+          WelcomeMessage::ProfileNameInputChanged,
+        )
+        .style(styles::TextInput::Primary)
+        .padding(8),
+      )
+      .push(
+        TextInput::new(
+          &mut self.host_input,
+          "Server host",
+          &self.host,
+          WelcomeMessage::HostInputChanged,
+        )
+        .style(styles::TextInput::Primary)
+        .padding(8),
+      )
       .push(
         TextInput::new(
           &mut self.api_key_input,
@@ -65,17 +167,28 @@ impl WelcomeScene {
         )
         .style(styles::TextInput::Primary)
         .padding(8),
-      )
-      .max_width(300)
-      .spacing(12)
-      .align_items(Align::Center);
+      );
 
     if let Some(error_msg) = self.error.as_ref() {
       welcome = welcome.push(Text::new(error_msg).color(Color::WHITE));
     }
 
-    if self.api_key.len() > 5 {
-      welcome = welcome.push(
+    let mut actions = Row::new().spacing(8);
+
+    if saved {
+      actions = actions.push(
+        Button::new(&mut self.forget_button, Text::new("Forget"))
+          .padding(8)
+          .style(styles::Button::Primary)
+          .on_press(WelcomeMessage::ForgetProfile),
+      );
+    }
+
+    if self.api_key.len() > 5
+      && !self.host.trim().is_empty()
+      && !self.profile_name.trim().is_empty()
+    {
+      actions = actions.push(
         Button::new(&mut self.next_button, Text::new("Next"))
           .padding(8)
           .style(styles::Button::Primary)
@@ -83,6 +196,8 @@ impl WelcomeScene {
       );
     }
 
+    welcome = welcome.push(actions);
+
     let container = Container::new(welcome)
       .width(Length::Fill)
       .height(Length::Fill)
@@ -0,0 +1,87 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::delay_for;
+
+use crate::api::ApiError;
+
+pub const MIN_CONCURRENCY: usize = 1;
+pub const MAX_CONCURRENCY: usize = 8;
+pub const DEFAULT_CONCURRENCY: usize = 2;
+
+pub const MAX_RETRIES: u32 = 4;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Keeps track of how many uploads may run side by side.
+///
+/// Queued files are handed out to free slots whenever an upload starts or
+/// finishes, until the queue is drained.
+#[derive(Debug)]
+pub struct UploadScheduler {
+  concurrency: usize,
+  running: bool,
+}
+
+impl Default for UploadScheduler {
+  fn default() -> Self {
+    Self {
+      concurrency: DEFAULT_CONCURRENCY,
+      running: false,
+    }
+  }
+}
+
+impl UploadScheduler {
+  pub fn start(&mut self) {
+    self.running = true;
+  }
+
+  pub fn stop(&mut self) {
+    self.running = false;
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.running
+  }
+
+  pub fn concurrency(&self) -> usize {
+    self.concurrency
+  }
+
+  pub fn set_concurrency(&mut self, concurrency: usize) {
+    self.concurrency = concurrency.clamp(MIN_CONCURRENCY, MAX_CONCURRENCY);
+  }
+
+  pub fn available_slots(&self, in_flight: usize) -> usize {
+    if self.running {
+      self.concurrency.saturating_sub(in_flight)
+    } else {
+      0
+    }
+  }
+}
+
+/// Runs `operation`, retrying transient failures with exponential backoff.
+///
+/// Rate limited requests are retried straight away, as the API's rate
+/// limiter already holds them back for as long as the server asked.
+pub async fn retry<T, F, Fut>(mut operation: F) -> Result<T, ApiError>
+where
+  F: FnMut() -> Fut,
+  Fut: Future<Output = Result<T, ApiError>>,
+{
+  let mut attempt = 0;
+
+  loop {
+    match operation().await {
+      Err(ApiError::RateLimited(_)) if attempt < MAX_RETRIES => {
+        attempt += 1;
+      }
+      Err(err) if err.is_transient() && attempt < MAX_RETRIES => {
+        delay_for(INITIAL_BACKOFF * 2u32.pow(attempt)).await;
+        attempt += 1;
+      }
+      result => return result,
+    }
+  }
+}
@@ -0,0 +1,234 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::{ApiError, MultipartUpload};
+use crate::config;
+use crate::digest;
+use crate::metadata::VideoMetadata;
+use crate::sniff::{self, VideoFormat};
+use crate::tags::{self, Tag};
+use crate::widgets::file::{File, FileState};
+
+const SESSION_FILE: &str = "session.json";
+/// Where versions before the data directory kept the session.
+const LEGACY_SESSION_PATH: &str = "mx-session.json";
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Session {
+  pub files: Vec<SessionFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionFile {
+  pub path: PathBuf,
+  pub state: FileState,
+  pub md5: Option<String>,
+  #[serde(default)]
+  pub size: Option<u64>,
+  #[serde(default)]
+  pub format: Option<VideoFormat>,
+  #[serde(default)]
+  pub metadata: VideoMetadata,
+  #[serde(deserialize_with = "tags::deserialize_stored")]
+  pub tags: Vec<Tag>,
+  #[serde(default)]
+  pub source: String,
+  #[serde(default)]
+  pub description: String,
+  #[serde(default)]
+  pub rules: Vec<String>,
+  #[serde(default)]
+  pub multipart: Option<MultipartUpload>,
+  #[serde(default)]
+  pub error: Option<ApiError>,
+  #[serde(default)]
+  pub invalid_reason: Option<String>,
+}
+
+impl Session {
+  pub fn new(files: &[File]) -> Self {
+    Self {
+      files: files.iter().map(SessionFile::from).collect(),
+    }
+  }
+}
+
+impl From<&File> for SessionFile {
+  fn from(file: &File) -> Self {
+    Self {
+      path: file.path.clone(),
+      state: file.state,
+      md5: file.md5.map(|digest| format!("{:x}", digest)),
+      size: file.size,
+      format: file.format,
+      metadata: file.metadata.clone(),
+      tags: file.tags.clone(),
+      source: file.source.clone(),
+      description: file.description.clone(),
+      rules: file.rules.clone(),
+      multipart: file.multipart.clone(),
+      error: file.error.clone(),
+      invalid_reason: file.invalid_reason.clone(),
+    }
+  }
+}
+
+impl SessionFile {
+  /// Turns a persisted entry back into a [`File`], resetting any state that
+  /// was interrupted by the app closing.
+  pub fn into_file(self, id: u64) -> File {
+    let md5 = self.md5.as_deref().and_then(digest::parse);
+
+    let state = match self.state {
+      FileState::Uploading => FileState::Queued,
+      FileState::Analyzing | FileState::Analyzed | FileState::CheckingDuplicate => {
+        if md5.is_some() {
+          FileState::Analyzed
+        } else {
+          FileState::Analyzing
+        }
+      }
+      state => state,
+    };
+
+    File {
+      id,
+      path: self.path.clone(),
+      state,
+      md5,
+      size: self
+        .size
+        .or_else(|| std::fs::metadata(&self.path).ok().map(|meta| meta.len())),
+      format: self
+        .format
+        .or_else(|| sniff::sniff(&self.path).ok().flatten()),
+      metadata: self.metadata,
+      tags: self.tags,
+      source: self.source,
+      description: self.description,
+      rules: self.rules,
+      multipart: self.multipart,
+      error: self.error,
+      invalid_reason: self.invalid_reason,
+      ..Default::default()
+    }
+  }
+}
+
+/// Where the session lives, e.g. `~/.local/share/mx/session.json` on
+/// Linux, so it is found whatever directory mx is started from.
+///
+/// Falls back to the working directory on platforms without a data
+/// directory.
+pub fn session_path() -> PathBuf {
+  dirs::data_dir()
+    .map(|dir| dir.join(config::SETTINGS_DIR))
+    .unwrap_or_default()
+    .join(SESSION_FILE)
+}
+
+/// Reads the session, or the one older versions left in the working
+/// directory. `None` if there is neither.
+///
+/// A session that cannot be read is moved aside to `session.json.broken`
+/// rather than being overwritten by the next save.
+pub fn read_session() -> Result<Option<Session>, anyhow::Error> {
+  let path = session_path();
+
+  if path.exists() {
+    read_session_from(&path)
+  } else {
+    read_session_from(Path::new(LEGACY_SESSION_PATH))
+  }
+}
+
+fn read_session_from(path: &Path) -> Result<Option<Session>, anyhow::Error> {
+  let contents = match std::fs::read_to_string(path) {
+    Ok(contents) => contents,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+    Err(err) => return Err(err.into()),
+  };
+
+  match serde_json::from_str(&contents) {
+    Ok(session) => Ok(Some(session)),
+    Err(err) => {
+      let broken_path = path.with_extension("json.broken");
+      std::fs::rename(path, &broken_path)?;
+
+      Err(anyhow::anyhow!(
+        "{}; it was moved to {}",
+        err,
+        broken_path.display()
+      ))
+    }
+  }
+}
+
+pub fn write_session(session: &Session) -> Result<(), anyhow::Error> {
+  write_session_to(session, &session_path())?;
+
+  // The session lives in the data directory from now on.
+  let _ = std::fs::remove_file(LEGACY_SESSION_PATH);
+  Ok(())
+}
+
+fn write_session_to(session: &Session, path: &Path) -> Result<(), anyhow::Error> {
+  let contents = serde_json::to_string_pretty(session)?;
+  config::write_atomic(path, contents.as_bytes())?;
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SESSION_FILE);
+    let file = File {
+      path: dir.path().join("clip.mp4"),
+      state: FileState::Uploading,
+      md5: Some(md5::compute(b"clip")),
+      size: Some(4),
+      description: "A clip".to_owned(),
+      ..Default::default()
+    };
+
+    write_session_to(&Session::new(&[file]), &path).unwrap();
+    let session = read_session_from(&path).unwrap().unwrap();
+    let restored = session.files.into_iter().next().unwrap().into_file(7);
+
+    assert_eq!(restored.id, 7);
+    assert_eq!(restored.path, dir.path().join("clip.mp4"));
+    // Interrupted uploads go back to the queue.
+    assert_eq!(restored.state, FileState::Queued);
+    assert_eq!(restored.md5, Some(md5::compute(b"clip")));
+    assert_eq!(restored.description, "A clip");
+  }
+
+  #[test]
+  fn missing_session_is_empty() {
+    let dir = tempfile::tempdir().unwrap();
+
+    assert!(read_session_from(&dir.path().join(SESSION_FILE))
+      .unwrap()
+      .is_none());
+  }
+
+  #[test]
+  fn broken_session_is_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(SESSION_FILE);
+    std::fs::write(&path, b"{ \"files\": [").unwrap();
+
+    assert!(read_session_from(&path).is_err());
+    assert!(!path.exists());
+    assert_eq!(
+      std::fs::read(dir.path().join("session.json.broken")).unwrap(),
+      b"{ \"files\": ["
+    );
+  }
+}
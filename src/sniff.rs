@@ -0,0 +1,287 @@
+use std::io::Read;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Bytes read from the start of a file to recognize it. Enough for three
+/// transport stream packets and the EBML header of Matroska files.
+const HEADER_LENGTH: usize = 512;
+
+const TS_PACKET_LENGTH: usize = 188;
+/// Blu-ray transport streams put a 4-byte timecode before each packet.
+const M2TS_PACKET_LENGTH: usize = 192;
+const TS_SYNC_BYTE: u8 = 0x47;
+
+/// Top-level atoms a QuickTime file without an `ftyp` atom can start with.
+const QUICKTIME_ATOMS: &[&[u8]] = &[b"moov", b"mdat", b"wide", b"free", b"skip", b"pnot"];
+
+/// Starts every ASF file: the GUID of its header object.
+const ASF_HEADER: &[u8] = &[
+  0x30, 0x26, 0xb2, 0x75, 0x8e, 0x66, 0xcf, 0x11, 0xa6, 0xd9, 0x00, 0xaa, 0x00, 0x62, 0xce, 0x6c,
+];
+/// GUIDs of the stream types an ASF stream properties object names.
+const ASF_VIDEO_MEDIA: &[u8] = &[
+  0xc0, 0xef, 0x19, 0xbc, 0x4d, 0x5b, 0xcf, 0x11, 0xa8, 0xfd, 0x00, 0x80, 0x5f, 0x5c, 0x44, 0x2b,
+];
+const ASF_AUDIO_MEDIA: &[u8] = &[
+  0x40, 0x9e, 0x69, 0xf8, 0x4d, 0x5b, 0xcf, 0x11, 0xa8, 0xfd, 0x00, 0x80, 0x5f, 0x5c, 0x44, 0x2b,
+];
+
+/// Identification headers of the video codecs Ogg files carry.
+const OGG_VIDEO_CODECS: &[&[u8]] = &[b"\x80theora", b"OVP80"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum VideoFormat {
+  Mp4,
+  QuickTime,
+  Matroska,
+  WebM,
+  Avi,
+  Flv,
+  MpegTs,
+  /// MPEG program stream, as on DVDs.
+  MpegPs,
+  /// Advanced Systems Format, the container of Windows Media Video.
+  Asf,
+  Ogg,
+}
+
+impl VideoFormat {
+  const ALL: &'static [VideoFormat] = &[
+    VideoFormat::Mp4,
+    VideoFormat::QuickTime,
+    VideoFormat::Matroska,
+    VideoFormat::WebM,
+    VideoFormat::Avi,
+    VideoFormat::Flv,
+    VideoFormat::MpegTs,
+    VideoFormat::MpegPs,
+    VideoFormat::Asf,
+    VideoFormat::Ogg,
+  ];
+
+  pub fn extensions(self) -> &'static [&'static str] {
+    match self {
+      VideoFormat::Mp4 => &["mp4", "m4v", "3gp", "3g2"],
+      VideoFormat::QuickTime => &["mov", "qt"],
+      VideoFormat::Matroska => &["mkv"],
+      VideoFormat::WebM => &["webm"],
+      VideoFormat::Avi => &["avi"],
+      VideoFormat::Flv => &["flv"],
+      VideoFormat::MpegTs => &["ts", "m2ts", "mts"],
+      VideoFormat::MpegPs => &["mpg", "mpeg", "vob"],
+      VideoFormat::Asf => &["wmv", "asf"],
+      VideoFormat::Ogg => &["ogv"],
+    }
+  }
+
+  pub fn matches_extension(self, path: &Path) -> bool {
+    path
+      .extension()
+      .and_then(|extension| extension.to_str())
+      .filter(|extension| {
+        self
+          .extensions()
+          .contains(&extension.to_lowercase().as_str())
+      })
+      .is_some()
+  }
+}
+
+pub fn has_video_extension(path: &Path) -> bool {
+  VideoFormat::ALL
+    .iter()
+    .any(|format| format.matches_extension(path))
+}
+
+impl std::fmt::Display for VideoFormat {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match *self {
+      VideoFormat::Mp4 => write!(f, "MP4"),
+      VideoFormat::QuickTime => write!(f, "QuickTime"),
+      VideoFormat::Matroska => write!(f, "Matroska"),
+      VideoFormat::WebM => write!(f, "WebM"),
+      VideoFormat::Avi => write!(f, "AVI"),
+      VideoFormat::Flv => write!(f, "FLV"),
+      VideoFormat::MpegTs => write!(f, "MPEG-TS"),
+      VideoFormat::MpegPs => write!(f, "MPEG-PS"),
+      VideoFormat::Asf => write!(f, "ASF"),
+      VideoFormat::Ogg => write!(f, "Ogg"),
+    }
+  }
+}
+
+/// Reads the start of the file at `path` to tell which video container it
+/// is, if any, regardless of its name.
+pub fn sniff(path: &Path) -> std::io::Result<Option<VideoFormat>> {
+  let mut header = Vec::with_capacity(HEADER_LENGTH);
+  std::fs::File::open(path)?
+    .take(HEADER_LENGTH as u64)
+    .read_to_end(&mut header)?;
+
+  Ok(detect(&header))
+}
+
+fn detect(header: &[u8]) -> Option<VideoFormat> {
+  if header.len() >= 12 && &header[4..8] == b"ftyp" {
+    // QuickTime files name themselves by their major brand.
+    return if &header[8..12] == b"qt  " {
+      Some(VideoFormat::QuickTime)
+    } else {
+      Some(VideoFormat::Mp4)
+    };
+  }
+
+  if header.len() >= 8 && QUICKTIME_ATOMS.contains(&&header[4..8]) {
+    return Some(VideoFormat::QuickTime);
+  }
+
+  if header.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) {
+    // The EBML header names the document type near its start.
+    let is_webm = header.windows(4).any(|window| window == b"webm");
+
+    return if is_webm {
+      Some(VideoFormat::WebM)
+    } else {
+      Some(VideoFormat::Matroska)
+    };
+  }
+
+  if header.len() >= 12 && header.starts_with(b"RIFF") && &header[8..12] == b"AVI " {
+    return Some(VideoFormat::Avi);
+  }
+
+  if header.starts_with(b"FLV\x01") {
+    return Some(VideoFormat::Flv);
+  }
+
+  if header.starts_with(&[0x00, 0x00, 0x01, 0xba]) {
+    return Some(VideoFormat::MpegPs);
+  }
+
+  if header.starts_with(ASF_HEADER) {
+    // Audio-only .wma files are ASF too. Their streams are usually listed
+    // early enough to tell; if not, the file gets the benefit of the doubt.
+    let audio_only = !contains(header, ASF_VIDEO_MEDIA) && contains(header, ASF_AUDIO_MEDIA);
+
+    return if audio_only {
+      None
+    } else {
+      Some(VideoFormat::Asf)
+    };
+  }
+
+  // Ogg is mostly used for audio, so it only counts with a video codec.
+  if header.starts_with(b"OggS") && OGG_VIDEO_CODECS.iter().any(|codec| contains(header, codec)) {
+    return Some(VideoFormat::Ogg);
+  }
+
+  if is_transport_stream(header, 0, TS_PACKET_LENGTH)
+    || is_transport_stream(header, 4, M2TS_PACKET_LENGTH)
+  {
+    return Some(VideoFormat::MpegTs);
+  }
+
+  None
+}
+
+fn contains(header: &[u8], needle: &[u8]) -> bool {
+  header.windows(needle.len()).any(|window| window == needle)
+}
+
+fn is_transport_stream(header: &[u8], offset: usize, packet_length: usize) -> bool {
+  (0..3).all(|packet| header.get(offset + packet * packet_length) == Some(&TS_SYNC_BYTE))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn with_length(mut header: Vec<u8>) -> Vec<u8> {
+    header.resize(HEADER_LENGTH, 0);
+    header
+  }
+
+  #[test]
+  fn detects_iso_and_quicktime() {
+    assert_eq!(
+      detect(b"\0\0\0\x18ftypisom\0\0\0\0"),
+      Some(VideoFormat::Mp4)
+    );
+    assert_eq!(
+      detect(b"\0\0\0\x14ftypqt  \0\0\0\0"),
+      Some(VideoFormat::QuickTime)
+    );
+    assert_eq!(detect(b"\0\0\0\x08wide"), Some(VideoFormat::QuickTime));
+  }
+
+  #[test]
+  fn detects_matroska_and_webm() {
+    let ebml = [0x1a, 0x45, 0xdf, 0xa3, 0x87, 0x42, 0x82, 0x84];
+
+    assert_eq!(
+      detect(&[&ebml[..], b"webm"].concat()),
+      Some(VideoFormat::WebM)
+    );
+    assert_eq!(
+      detect(&[&ebml[..], b"matroska"].concat()),
+      Some(VideoFormat::Matroska)
+    );
+  }
+
+  #[test]
+  fn detects_other_containers() {
+    assert_eq!(detect(b"RIFF\0\0\0\0AVI LIST"), Some(VideoFormat::Avi));
+    assert_eq!(detect(b"FLV\x01\x05"), Some(VideoFormat::Flv));
+
+    let mut ts = with_length(Vec::new());
+    for packet in 0..3 {
+      ts[packet * TS_PACKET_LENGTH] = TS_SYNC_BYTE;
+    }
+    assert_eq!(detect(&ts), Some(VideoFormat::MpegTs));
+
+    let mut m2ts = with_length(Vec::new());
+    for packet in 0..3 {
+      m2ts[4 + packet * M2TS_PACKET_LENGTH] = TS_SYNC_BYTE;
+    }
+    assert_eq!(detect(&m2ts), Some(VideoFormat::MpegTs));
+  }
+
+  #[test]
+  fn detects_program_streams_asf_and_ogg() {
+    assert_eq!(detect(b"\0\0\x01\xba\x44\0\x04"), Some(VideoFormat::MpegPs));
+
+    let video_asf = [ASF_HEADER, &[0; 14], ASF_VIDEO_MEDIA, ASF_AUDIO_MEDIA].concat();
+    assert_eq!(detect(&video_asf), Some(VideoFormat::Asf));
+    // The stream list can be beyond the header read.
+    assert_eq!(detect(ASF_HEADER), Some(VideoFormat::Asf));
+
+    let ogg = [&b"OggS\0\x02"[..], &[0; 22], b"\x80theora"].concat();
+    assert_eq!(detect(&ogg), Some(VideoFormat::Ogg));
+  }
+
+  #[test]
+  fn rejects_audio_only_containers() {
+    let wma = [ASF_HEADER, &[0; 14], ASF_AUDIO_MEDIA].concat();
+    assert_eq!(detect(&wma), None);
+
+    let vorbis = [&b"OggS\0\x02"[..], &[0; 22], b"\x01vorbis"].concat();
+    assert_eq!(detect(&vorbis), None);
+  }
+
+  #[test]
+  fn rejects_non_videos() {
+    assert_eq!(detect(b""), None);
+    assert_eq!(detect(b"<!DOCTYPE html><html>"), None);
+    assert_eq!(detect(&with_length(b"GIF89a".to_vec())), None);
+  }
+
+  #[test]
+  fn matches_extensions_case_insensitively() {
+    assert!(VideoFormat::Mp4.matches_extension(Path::new("clip.MP4")));
+    assert!(VideoFormat::MpegTs.matches_extension(Path::new("clip.m2ts")));
+    assert!(VideoFormat::Mp4.matches_extension(Path::new("clip.3gp")));
+    assert!(!VideoFormat::WebM.matches_extension(Path::new("clip.mp4")));
+    assert!(!VideoFormat::Mp4.matches_extension(Path::new("clip")));
+  }
+}
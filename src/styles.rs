@@ -1,4 +1,4 @@
-use iced::{button, container, text_input, Background, Color, Text};
+use iced::{button, container, progress_bar, text_input, Background, Color, Text};
 
 const SURFACE: Color = Color::from_rgb(
   0x21 as f32 / 255.0,
@@ -68,6 +68,9 @@ impl container::StyleSheet for Container {
 pub enum Button {
   Primary,
   Transparent,
+  Chip,
+  /// A tag only some of the selected files have.
+  PartialChip,
 }
 
 impl button::StyleSheet for Button {
@@ -76,9 +79,14 @@ impl button::StyleSheet for Button {
       background: match self {
         Button::Primary => Some(Background::Color(ACTIVE)),
         Button::Transparent => None,
+        Button::Chip => Some(Background::Color(SURFACE)),
+        Button::PartialChip => None,
       },
       border_radius: 2,
-      text_color: Color::WHITE,
+      text_color: match self {
+        Button::PartialChip => Color::from_rgb(0.6, 0.6, 0.6),
+        _ => Color::WHITE,
+      },
       ..button::Style::default()
     }
   }
@@ -88,6 +96,8 @@ impl button::StyleSheet for Button {
       background: match self {
         Button::Primary => Some(Background::Color(HOVERED)),
         Button::Transparent => Some(Background::Color(HOVERED)),
+        Button::Chip => Some(Background::Color(HOVERED)),
+        Button::PartialChip => Some(Background::Color(HOVERED)),
       },
       ..self.active()
     }
@@ -125,3 +135,15 @@ impl text_input::StyleSheet for TextInput {
     Color::from_rgb(0.8, 0.8, 1.0)
   }
 }
+
+pub struct ProgressBar;
+
+impl progress_bar::StyleSheet for ProgressBar {
+  fn style(&self) -> progress_bar::Style {
+    progress_bar::Style {
+      background: Background::Color(ACTIVE),
+      bar: Background::Color(Color::from_rgb(0.5, 0.5, 0.5)),
+      border_radius: 2,
+    }
+  }
+}
@@ -0,0 +1,268 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+/// Characters used by searches on the archive, which tags cannot contain.
+const INVALID_CHARACTERS: &[char] = &[',', '*'];
+
+/// Search operators, which tags cannot start with.
+const INVALID_PREFIXES: &[char] = &['-', '~'];
+
+/// A single normalized tag, e.g. `long_hair`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Tag(String);
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum TagError {
+  #[error("Tag is empty")]
+  Empty,
+  #[error("\"{0}\" contains '{1}', which tags cannot contain")]
+  InvalidCharacter(String, char),
+  #[error("\"{0}\" starts with '{1}', which tags cannot start with")]
+  InvalidPrefix(String, char),
+}
+
+impl Tag {
+  /// Normalizes `raw` into a tag: lowercased, with each run of whitespace
+  /// turned into an underscore.
+  pub fn parse(raw: &str) -> Result<Self, TagError> {
+    let name = raw
+      .split_whitespace()
+      .collect::<Vec<_>>()
+      .join("_")
+      .to_lowercase();
+
+    if name.is_empty() {
+      return Err(TagError::Empty);
+    }
+
+    let invalid = name
+      .chars()
+      .find(|c| c.is_control() || INVALID_CHARACTERS.contains(c));
+
+    if let Some(c) = invalid {
+      return Err(TagError::InvalidCharacter(name, c));
+    }
+
+    if let Some(c) = name.chars().next().filter(|c| INVALID_PREFIXES.contains(c)) {
+      return Err(TagError::InvalidPrefix(name, c));
+    }
+
+    Ok(Tag(name))
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+}
+
+impl std::fmt::Display for Tag {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl TryFrom<String> for Tag {
+  type Error = TagError;
+
+  fn try_from(raw: String) -> Result<Self, Self::Error> {
+    Tag::parse(&raw)
+  }
+}
+
+impl From<Tag> for String {
+  fn from(tag: Tag) -> Self {
+    tag.0
+  }
+}
+
+/// Parses tags typed into the tag editor, separated by commas so a tag can
+/// be typed with spaces, e.g. `long hair, blue eyes`.
+pub fn parse_input(input: &str) -> Result<Vec<Tag>, TagError> {
+  let mut tags = Vec::new();
+
+  for raw in input.split(',').filter(|raw| !raw.trim().is_empty()) {
+    add(&mut tags, Tag::parse(raw)?);
+  }
+
+  Ok(tags)
+}
+
+/// Parses a space-separated list in the archive's format, as found in the
+/// settings, on the command line and in sidecar files.
+pub fn parse_list(list: &str) -> Result<Vec<Tag>, TagError> {
+  let mut tags = Vec::new();
+
+  for raw in list.split_whitespace() {
+    add(&mut tags, Tag::parse(raw)?);
+  }
+
+  Ok(tags)
+}
+
+pub fn join(tags: &[Tag]) -> String {
+  tags.iter().map(Tag::as_str).collect::<Vec<_>>().join(" ")
+}
+
+pub fn add(tags: &mut Vec<Tag>, tag: Tag) {
+  if !tags.contains(&tag) {
+    tags.push(tag);
+  }
+}
+
+/// Reads stored tags, either as a list or as a space-separated string as
+/// older sessions and hand-written settings have them. Tags that are no
+/// longer valid are dropped rather than failing the whole file.
+pub fn deserialize_stored<'de, D>(deserializer: D) -> Result<Vec<Tag>, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum StoredTags {
+    List(Vec<String>),
+    Text(String),
+  }
+
+  let raw_tags = match StoredTags::deserialize(deserializer)? {
+    StoredTags::List(list) => list,
+    StoredTags::Text(text) => text.split_whitespace().map(str::to_owned).collect(),
+  };
+
+  let mut tags = Vec::new();
+
+  for tag in raw_tags.iter().filter_map(|raw| Tag::parse(raw).ok()) {
+    add(&mut tags, tag);
+  }
+
+  Ok(tags)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn names(tags: &[Tag]) -> Vec<&str> {
+    tags.iter().map(Tag::as_str).collect()
+  }
+
+  #[test]
+  fn parses_tags() {
+    let cases: &[(&str, Result<&str, TagError>)] = &[
+      ("hair", Ok("hair")),
+      ("Long Hair", Ok("long_hair")),
+      ("  long \t hair\n", Ok("long_hair")),
+      ("already_joined", Ok("already_joined")),
+      ("rock'n'roll", Ok("rock'n'roll")),
+      ("", Err(TagError::Empty)),
+      ("   ", Err(TagError::Empty)),
+      (
+        "a,b",
+        Err(TagError::InvalidCharacter("a,b".to_owned(), ',')),
+      ),
+      (
+        "Wild*",
+        Err(TagError::InvalidCharacter("wild*".to_owned(), '*')),
+      ),
+      (
+        "-excluded",
+        Err(TagError::InvalidPrefix("-excluded".to_owned(), '-')),
+      ),
+      (
+        "~maybe",
+        Err(TagError::InvalidPrefix("~maybe".to_owned(), '~')),
+      ),
+      // Only the start counts as a prefix.
+      ("half-pipe", Ok("half-pipe")),
+    ];
+
+    for (raw, expected) in cases {
+      let parsed = Tag::parse(raw);
+      assert_eq!(
+        parsed.as_ref().map(Tag::as_str),
+        expected.as_ref().map(|name| *name),
+        "parsing {:?}",
+        raw
+      );
+    }
+  }
+
+  #[test]
+  fn parses_input() {
+    let cases: &[(&str, Vec<&str>)] = &[
+      ("", vec![]),
+      ("long hair, Blue Eyes", vec!["long_hair", "blue_eyes"]),
+      ("hair,, ,hair, HAIR", vec!["hair"]),
+      (" trailing, ", vec!["trailing"]),
+    ];
+
+    for (input, expected) in cases {
+      assert_eq!(
+        names(&parse_input(input).unwrap()),
+        *expected,
+        "{:?}",
+        input
+      );
+    }
+
+    assert_eq!(
+      parse_input("fine, -bad"),
+      Err(TagError::InvalidPrefix("-bad".to_owned(), '-'))
+    );
+  }
+
+  #[test]
+  fn parses_lists() {
+    let cases: &[(&str, Vec<&str>)] = &[
+      ("", vec![]),
+      ("spin  Event\tspin\n", vec!["spin", "event"]),
+      ("long_hair blue_eyes", vec!["long_hair", "blue_eyes"]),
+    ];
+
+    for (list, expected) in cases {
+      assert_eq!(names(&parse_list(list).unwrap()), *expected, "{:?}", list);
+    }
+
+    assert_eq!(
+      parse_list("fine ~bad"),
+      Err(TagError::InvalidPrefix("~bad".to_owned(), '~'))
+    );
+    assert_eq!(names(&parse_list("a b c").unwrap()), vec!["a", "b", "c"]);
+    assert_eq!(join(&parse_list("a b c").unwrap()), "a b c");
+  }
+
+  #[derive(Deserialize)]
+  struct Stored {
+    #[serde(deserialize_with = "deserialize_stored")]
+    tags: Vec<Tag>,
+  }
+
+  #[test]
+  fn reads_stored_tags() {
+    let cases: &[(&str, Vec<&str>)] = &[
+      (
+        r#"{"tags": ["long_hair", "Blue Eyes"]}"#,
+        vec!["long_hair", "blue_eyes"],
+      ),
+      // Older sessions stored one space-separated string.
+      (
+        r#"{"tags": "long_hair  spin Spin"}"#,
+        vec!["long_hair", "spin"],
+      ),
+      (r#"{"tags": ""}"#, vec![]),
+      (r#"{"tags": []}"#, vec![]),
+      // Invalid tags are dropped, not the whole file.
+      (r#"{"tags": ["fine", "-bad", "a,b", ""]}"#, vec!["fine"]),
+      (r#"{"tags": "fine ~bad"}"#, vec!["fine"]),
+    ];
+
+    for (json, expected) in cases {
+      let stored: Stored = serde_json::from_str(json).unwrap();
+      assert_eq!(names(&stored.tags), *expected, "{}", json);
+    }
+
+    assert!(serde_json::from_str::<Stored>(r#"{"tags": 3}"#).is_err());
+  }
+}
@@ -0,0 +1,331 @@
+use std::hash::Hash;
+use std::path::PathBuf;
+
+use futures::channel::mpsc;
+use futures::stream::{self, BoxStream, StreamExt};
+use iced_native::subscription::Recipe;
+
+use crate::api::{ApiError, ArchiveApi, CompletedPart, MultipartUpload};
+use crate::message::Message;
+use crate::progress;
+use crate::scheduler;
+use crate::tags;
+use crate::widgets::file::{File, FileMessage};
+
+/// Files at least this large are sent in parts so they can be resumed.
+pub const CHUNKED_UPLOAD_THRESHOLD: u64 = 512 * 1024 * 1024;
+
+const PART_SIZE: u64 = 64 * 1024 * 1024;
+
+pub enum UploadEvent {
+  Progress(u64),
+  MultipartStarted(MultipartUpload),
+  PartCompleted(CompletedPart),
+}
+
+impl From<UploadEvent> for FileMessage {
+  fn from(event: UploadEvent) -> Self {
+    match event {
+      UploadEvent::Progress(bytes_sent) => FileMessage::Uploaded(bytes_sent),
+      UploadEvent::MultipartStarted(upload) => FileMessage::MultipartStarted(upload),
+      UploadEvent::PartCompleted(part) => FileMessage::PartUploaded(part),
+    }
+  }
+}
+
+pub struct FileUpload<A: ArchiveApi> {
+  id: u64,
+  api: A,
+  path: PathBuf,
+  size: u64,
+  md5: String,
+  tags: String,
+  source: String,
+  description: String,
+  multipart: Option<MultipartUpload>,
+}
+
+impl<A: ArchiveApi> FileUpload<A> {
+  pub fn new(api: &A, file: &File) -> Self {
+    Self {
+      id: file.id,
+      api: api.clone(),
+      path: file.path.clone(),
+      size: file.size.unwrap_or_default(),
+      md5: file.get_md5(),
+      tags: tags::join(&file.tags),
+      source: file.source.clone(),
+      description: file.description.clone(),
+      multipart: file.multipart.clone(),
+    }
+  }
+
+  pub async fn run(self, sender: mpsc::UnboundedSender<UploadEvent>) -> Result<(), ApiError> {
+    if self.size >= CHUNKED_UPLOAD_THRESHOLD {
+      return self.run_chunked(sender).await;
+    }
+
+    let result = scheduler::retry(|| self.api.create_upload(&self.path, &self.md5)).await?;
+    scheduler::retry(|| {
+      self.api.put_file(
+        &self.path,
+        &result.url,
+        progress_reporter(sender.clone(), 0),
+      )
+    })
+    .await?;
+    scheduler::retry(|| {
+      self
+        .api
+        .finalize_upload(&result.id, &self.tags, &self.source, &self.description)
+    })
+    .await?;
+
+    Ok(())
+  }
+
+  /// Sends the file part by part, skipping any part the server has already
+  /// confirmed from a previous attempt.
+  async fn run_chunked(self, sender: mpsc::UnboundedSender<UploadEvent>) -> Result<(), ApiError> {
+    let resumed = match &self.multipart {
+      Some(upload) => match scheduler::retry(|| self.api.confirmed_parts(upload)).await {
+        Ok(parts) => Some(MultipartUpload {
+          parts,
+          ..upload.clone()
+        }),
+        // The server no longer knows about this upload, so start over.
+        Err(ApiError::NotFound(_)) => None,
+        Err(err) => return Err(err),
+      },
+      None => None,
+    };
+
+    let mut upload = match resumed {
+      Some(upload) => upload,
+      None => {
+        scheduler::retry(|| self.api.create_multipart(&self.path, &self.md5, PART_SIZE)).await?
+      }
+    };
+
+    let _ = sender.unbounded_send(UploadEvent::MultipartStarted(upload.clone()));
+
+    for part_number in 1..=upload.part_count(self.size) {
+      if upload
+        .parts
+        .iter()
+        .any(|part| part.part_number == part_number)
+      {
+        continue;
+      }
+
+      // Confirmed parts need not be the first ones, e.g. after a retry.
+      let offset = upload.confirmed_bytes(self.size);
+      let part = scheduler::retry(|| {
+        self.api.upload_part(
+          &upload,
+          &self.path,
+          part_number,
+          progress_reporter(sender.clone(), offset),
+        )
+      })
+      .await?;
+
+      let _ = sender.unbounded_send(UploadEvent::PartCompleted(part.clone()));
+      upload.parts.push(part);
+    }
+
+    scheduler::retry(|| self.api.complete_multipart(&upload)).await?;
+    scheduler::retry(|| {
+      self
+        .api
+        .finalize_upload(&upload.id, &self.tags, &self.source, &self.description)
+    })
+    .await?;
+
+    Ok(())
+  }
+}
+
+fn progress_reporter(
+  sender: mpsc::UnboundedSender<UploadEvent>,
+  offset: u64,
+) -> impl FnMut(u64) + Send + Sync + 'static {
+  progress::throttled(move |bytes_sent| {
+    let _ = sender.unbounded_send(UploadEvent::Progress(offset + bytes_sent));
+  })
+}
+
+impl<A, H, I> Recipe<H, I> for FileUpload<A>
+where
+  A: ArchiveApi,
+  H: std::hash::Hasher,
+{
+  type Output = Message;
+
+  fn hash(&self, state: &mut H) {
+    std::any::TypeId::of::<Self>().hash(state);
+    self.id.hash(state);
+  }
+
+  fn stream(self: Box<Self>, _input: BoxStream<'static, I>) -> BoxStream<'static, Message> {
+    let id = self.id;
+    let (sender, receiver) = mpsc::unbounded();
+
+    let progress = receiver.map(move |event: UploadEvent| Message::FileMessage(id, event.into()));
+
+    let result = stream::once(async move {
+      match self.run(sender).await {
+        Ok(_) => Message::SuccessfulUpload(id),
+        Err(err) => Message::FailedUpload(id, err),
+      }
+    });
+
+    Box::pin(stream::select(progress, result))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use futures::future::{self, Either};
+
+  use super::*;
+  use crate::api::FakeApi;
+
+  /// A file just over the chunked upload threshold. It is sparse, as the
+  /// fake archive only looks at its size.
+  fn large_file(dir: &tempfile::TempDir) -> File {
+    let path = dir.path().join("large.mp4");
+    let size = CHUNKED_UPLOAD_THRESHOLD + PART_SIZE / 2;
+    std::fs::File::create(&path).unwrap().set_len(size).unwrap();
+
+    File {
+      id: 1,
+      path,
+      size: Some(size),
+      md5: Some(md5::compute(b"large")),
+      ..Default::default()
+    }
+  }
+
+  /// Runs the upload of `file` until it finishes or `stop_after_parts`
+  /// parts were sent, applying its events to `file` like the app does.
+  async fn run_upload(
+    api: &FakeApi,
+    file: &mut File,
+    stop_after_parts: Option<usize>,
+  ) -> (Option<Result<(), ApiError>>, Vec<UploadEvent>) {
+    let (sender, mut receiver) = mpsc::unbounded();
+    let run = FileUpload::new(api, file).run(sender);
+
+    let collect = async {
+      let mut events = Vec::new();
+      let mut parts = 0;
+
+      while let Some(event) = receiver.next().await {
+        if let UploadEvent::PartCompleted(_) = event {
+          parts += 1;
+        }
+
+        events.push(event);
+
+        if Some(parts) == stop_after_parts {
+          break;
+        }
+      }
+
+      events
+    };
+
+    futures::pin_mut!(run, collect);
+
+    let (result, events) = match future::select(run, collect).await {
+      Either::Left((result, collect)) => (Some(result), collect.await),
+      // Dropping the upload interrupts it, like removing it from the
+      // subscriptions does.
+      Either::Right((events, _)) => (None, events),
+    };
+
+    for event in &events {
+      file.update(clone_event(event).into());
+    }
+
+    (result, events)
+  }
+
+  fn clone_event(event: &UploadEvent) -> UploadEvent {
+    match event {
+      UploadEvent::Progress(bytes) => UploadEvent::Progress(*bytes),
+      UploadEvent::MultipartStarted(upload) => UploadEvent::MultipartStarted(upload.clone()),
+      UploadEvent::PartCompleted(part) => UploadEvent::PartCompleted(part.clone()),
+    }
+  }
+
+  fn completed_parts(events: &[UploadEvent]) -> Vec<u64> {
+    events
+      .iter()
+      .filter_map(|event| match event {
+        UploadEvent::PartCompleted(part) => Some(part.part_number),
+        _ => None,
+      })
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn resumes_interrupted_multipart_upload() {
+    let dir = tempfile::tempdir().unwrap();
+    let api = FakeApi::new(Duration::from_millis(0), None);
+    let mut file = large_file(&dir);
+    let part_count = 9;
+
+    let (result, events) = run_upload(&api, &mut file, Some(3)).await;
+    assert!(result.is_none());
+    assert_eq!(completed_parts(&events), vec![1, 2, 3]);
+    assert_eq!(file.multipart.as_ref().unwrap().parts.len(), 3);
+
+    let (result, events) = run_upload(&api, &mut file, None).await;
+    assert_eq!(result, Some(Ok(())));
+    assert_eq!(
+      completed_parts(&events),
+      (4..=part_count).collect::<Vec<_>>()
+    );
+
+    let checksums = vec![file.get_md5()];
+    assert_eq!(api.check_checksums(&checksums).await.unwrap(), checksums);
+  }
+
+  #[tokio::test]
+  async fn resumed_progress_counts_confirmed_parts_by_length() {
+    let dir = tempfile::tempdir().unwrap();
+    let api = FakeApi::new(Duration::from_millis(0), None);
+    let mut file = large_file(&dir);
+    let size = file.size.unwrap();
+
+    // Only the short last part made it before the upload was interrupted.
+    let mut upload = api
+      .create_multipart(&file.path, &file.get_md5(), PART_SIZE)
+      .await
+      .unwrap();
+    let last_part = upload.part_count(size);
+    let part = api
+      .upload_part(&upload, &file.path, last_part, |_| {})
+      .await
+      .unwrap();
+    upload.parts.push(part);
+    file.multipart = Some(upload);
+
+    let (result, events) = run_upload(&api, &mut file, None).await;
+    assert_eq!(result, Some(Ok(())));
+
+    let first_progress = events.iter().find_map(|event| match event {
+      UploadEvent::Progress(bytes) => Some(*bytes),
+      _ => None,
+    });
+    assert_eq!(first_progress, Some(PART_SIZE / 2 + PART_SIZE / 10));
+    assert!(events.iter().all(|event| match event {
+      UploadEvent::Progress(bytes) => *bytes <= size,
+      _ => true,
+    }));
+  }
+}
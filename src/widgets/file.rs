@@ -1,15 +1,28 @@
+use std::cmp::Ordering;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use iced::{
-  button, text_input, Button, Column, Container, Element, Length, Row, VerticalAlignment,
+  button, text_input, Button, Color, Column, Container, Element, Length, ProgressBar, Row, Text,
+  VerticalAlignment,
 };
+use serde::{Deserialize, Serialize};
 
+use crate::api::{ApiError, CompletedPart, MultipartUpload};
+use crate::hash_cache::Fingerprint;
 use crate::message::Message;
+use crate::metadata::{self, MetadataError, VideoMetadata};
+use crate::sniff::VideoFormat;
 use crate::styles;
+use crate::tags::{self, Tag};
 use crate::FileSelection;
 
+/// Read size while hashing. Large reads keep the disk streaming instead of
+/// seeking between files.
+const HASH_BUFFER_SIZE: usize = 1024 * 1024;
+
 #[allow(dead_code)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum FileState {
   Analyzing,
   Analyzed,
@@ -20,6 +33,10 @@ pub enum FileState {
   Failed,
   Duplicate,
   Queued,
+  Paused,
+  Cancelled,
+  /// Broken or incomplete, so it is never uploaded.
+  Invalid,
 }
 
 impl Default for FileState {
@@ -40,6 +57,9 @@ impl std::fmt::Display for FileState {
       FileState::Failed => write!(f, "Failed"),
       FileState::Duplicate => write!(f, "Duplicate"),
       FileState::Queued => write!(f, "Queued"),
+      FileState::Paused => write!(f, "Paused"),
+      FileState::Cancelled => write!(f, "Cancelled"),
+      FileState::Invalid => write!(f, "Invalid"),
     }
   }
 }
@@ -50,20 +70,155 @@ pub struct File {
   pub path: PathBuf,
   pub state: FileState,
   pub md5: Option<md5::Digest>,
-  pub tags: String,
+  pub size: Option<u64>,
+  /// The container the contents are in, whatever the name says.
+  pub format: Option<VideoFormat>,
+  pub metadata: VideoMetadata,
+  pub tags: Vec<Tag>,
+  /// Where the video was found, sent along as the post's source.
+  pub source: String,
+  pub description: String,
+  pub rules: Vec<String>,
+  pub progress: Option<UploadProgress>,
+  pub hashed_bytes: Option<u64>,
+  /// The hash remembered from an earlier run, if the file is unchanged.
+  pub cached_md5: Option<md5::Digest>,
+  pub multipart: Option<MultipartUpload>,
+  pub error: Option<ApiError>,
+  pub invalid_reason: Option<String>,
   pub tag_input: text_input::State,
   pub button: button::State,
 }
 
 #[derive(Debug, Clone)]
 pub enum FileMessage {
+  Hashed(u64),
   Analyzed(FileAnalysis),
+  Uploaded(u64),
+  MultipartStarted(MultipartUpload),
+  PartUploaded(CompletedPart),
 }
 
 #[derive(Debug, Clone)]
 pub struct FileAnalysis {
   pub id: u64,
   md5: md5::Digest,
+  size: u64,
+  metadata: VideoMetadata,
+  invalid_reason: Option<String>,
+  /// What the file looked like before it was read, if it did not change
+  /// while it was.
+  fingerprint: Option<Fingerprint>,
+}
+
+impl FileAnalysis {
+  /// What to store in the hash cache along with the hash. `None` if the
+  /// file changed while it was being read.
+  pub fn fingerprint(&self) -> Option<&Fingerprint> {
+    self.fingerprint.as_ref()
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UploadProgress {
+  pub bytes_sent: u64,
+  pub total_bytes: u64,
+  pub started_at: Instant,
+  /// Bytes already confirmed by a previous attempt when this one started.
+  pub resumed_from: u64,
+}
+
+impl UploadProgress {
+  pub fn new(total_bytes: u64) -> Self {
+    Self::resumed(total_bytes, 0)
+  }
+
+  pub fn resumed(total_bytes: u64, resumed_from: u64) -> Self {
+    Self {
+      bytes_sent: resumed_from,
+      total_bytes,
+      started_at: Instant::now(),
+      resumed_from,
+    }
+  }
+
+  pub fn percent(&self) -> f32 {
+    if self.total_bytes == 0 {
+      0.0
+    } else {
+      self.bytes_sent as f32 / self.total_bytes as f32 * 100.0
+    }
+  }
+
+  pub fn speed(&self) -> f64 {
+    let elapsed = self.started_at.elapsed().as_secs_f64();
+
+    if elapsed > 0.0 {
+      self.bytes_sent.saturating_sub(self.resumed_from) as f64 / elapsed
+    } else {
+      0.0
+    }
+  }
+
+  pub fn eta(&self) -> Option<Duration> {
+    let speed = self.speed();
+
+    if speed > 0.0 {
+      let remaining = self.total_bytes.saturating_sub(self.bytes_sent) as f64;
+      Some(Duration::from_secs_f64(remaining / speed))
+    } else {
+      None
+    }
+  }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+  const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+  let mut value = bytes as f64;
+  let mut unit = 0;
+
+  while value >= 1024.0 && unit < UNITS.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+
+  if unit == 0 {
+    format!("{} {}", bytes, UNITS[unit])
+  } else {
+    format!("{:.1} {}", value, UNITS[unit])
+  }
+}
+
+/// Formats a number of bits with decimal units, as bitrates are given.
+pub fn format_bits(bits: u64) -> String {
+  const UNITS: [&str; 4] = ["b", "kb", "Mb", "Gb"];
+
+  let mut value = bits as f64;
+  let mut unit = 0;
+
+  while value >= 1000.0 && unit < UNITS.len() - 1 {
+    value /= 1000.0;
+    unit += 1;
+  }
+
+  if unit == 0 {
+    format!("{} {}", bits, UNITS[unit])
+  } else {
+    format!("{:.1} {}", value, UNITS[unit])
+  }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+  let seconds = duration.as_secs();
+
+  if seconds >= 3600 {
+    format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60)
+  } else if seconds >= 60 {
+    format!("{}m{:02}s", seconds / 60, seconds % 60)
+  } else {
+    format!("{}s", seconds)
+  }
 }
 
 #[derive(Debug, Clone)]
@@ -72,33 +227,183 @@ pub enum AnalyzeError {
   FileRead,
 }
 
+impl std::fmt::Display for AnalyzeError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match *self {
+      AnalyzeError::FileOpen => write!(f, "Could not open the file"),
+      AnalyzeError::FileRead => write!(f, "Could not read the file"),
+    }
+  }
+}
+
 pub type AnalyzeResult = Result<FileAnalysis, AnalyzeError>;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortColumn {
+  Name,
+  Status,
+  Type,
+  Duration,
+  Resolution,
+  FrameRate,
+  Bitrate,
+  Size,
+}
+
+impl SortColumn {
+  const ALL: [SortColumn; 8] = [
+    SortColumn::Name,
+    SortColumn::Status,
+    SortColumn::Type,
+    SortColumn::Duration,
+    SortColumn::Resolution,
+    SortColumn::FrameRate,
+    SortColumn::Bitrate,
+    SortColumn::Size,
+  ];
+
+  fn label(self) -> &'static str {
+    match self {
+      SortColumn::Name => "File Name",
+      SortColumn::Status => "Status",
+      SortColumn::Type => "Type",
+      SortColumn::Duration => "Duration",
+      SortColumn::Resolution => "Resolution",
+      SortColumn::FrameRate => "FPS",
+      SortColumn::Bitrate => "Bitrate",
+      SortColumn::Size => "Size",
+    }
+  }
+
+  /// Orders files by this column, those without a value first.
+  fn compare(self, a: &File, b: &File) -> Ordering {
+    match self {
+      SortColumn::Name => a.file_name().cmp(b.file_name()),
+      SortColumn::Status => a.state.to_string().cmp(&b.state.to_string()),
+      SortColumn::Type => {
+        let name = |file: &File| file.format.map(|format| format.to_string());
+        name(a).cmp(&name(b))
+      }
+      SortColumn::Duration => a.metadata.duration.cmp(&b.metadata.duration),
+      SortColumn::Resolution => a.metadata.pixels().cmp(&b.metadata.pixels()),
+      SortColumn::FrameRate => a
+        .metadata
+        .frame_rate
+        .partial_cmp(&b.metadata.frame_rate)
+        .unwrap_or(Ordering::Equal),
+      SortColumn::Bitrate => a.bitrate().cmp(&b.bitrate()),
+      SortColumn::Size => a.size.cmp(&b.size),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sort {
+  pub column: SortColumn,
+  pub descending: bool,
+}
+
+impl Sort {
+  pub fn compare(self, a: &File, b: &File) -> Ordering {
+    let ordering = self.column.compare(a, b);
+
+    if self.descending {
+      ordering.reverse()
+    } else {
+      ordering
+    }
+  }
+}
+
+/// Buttons of the sortable column headers, in the order of
+/// [`SortColumn::ALL`].
+#[derive(Debug, Default)]
+pub struct SortHeaders {
+  buttons: [button::State; SortColumn::ALL.len()],
+}
+
+fn sort_header(
+  state: &mut button::State,
+  column: SortColumn,
+  sort: Option<Sort>,
+) -> Button<'_, Message> {
+  let label = match sort {
+    Some(sort) if sort.column == column && sort.descending => format!("{} v", column.label()),
+    Some(sort) if sort.column == column => format!("{} ^", column.label()),
+    _ => column.label().to_owned(),
+  };
+
+  Button::new(
+    state,
+    styles::text(label).vertical_alignment(VerticalAlignment::Center),
+  )
+  .style(styles::Button::Transparent)
+  .padding(0)
+  .on_press(Message::SortBy(column))
+}
+
+fn cell<'a>(text: String, selected: bool) -> Container<'a, Message> {
+  Container::new(styles::text(text))
+    .padding(2)
+    .style(styles::HoveredContainer::new(selected))
+}
+
 pub fn file_index<'a>(
   file_selection: &FileSelection,
+  sort: Option<Sort>,
+  headers: &'a mut SortHeaders,
   files: Vec<&'a mut File>,
 ) -> Element<'a, Message> {
-  let mut file_names = Column::new()
-    .spacing(2)
-    .push(styles::text("File Name").vertical_alignment(VerticalAlignment::Center));
-  let mut status = Column::new()
+  let mut headers = SortColumn::ALL
+    .iter()
+    .zip(headers.buttons.iter_mut())
+    .map(|(column, state)| sort_header(state, *column, sort));
+
+  let mut file_names = Column::new().spacing(2).push(headers.next().unwrap());
+  let mut status = Column::new().spacing(2).push(headers.next().unwrap());
+  let mut format = Column::new().spacing(2).push(headers.next().unwrap());
+  let mut duration = Column::new().spacing(2).push(headers.next().unwrap());
+  let mut resolution = Column::new().spacing(2).push(headers.next().unwrap());
+  let mut frame_rate = Column::new().spacing(2).push(headers.next().unwrap());
+  let mut bitrate = Column::new().spacing(2).push(headers.next().unwrap());
+  let mut size = Column::new().spacing(2).push(headers.next().unwrap());
+  let mut codecs = Column::new()
     .spacing(2)
-    .push(styles::text("Status").vertical_alignment(VerticalAlignment::Center));
+    .push(styles::text("Codecs").vertical_alignment(VerticalAlignment::Center));
   let mut md5 = Column::new()
     .spacing(2)
     .push(styles::text("MD5").vertical_alignment(VerticalAlignment::Center));
   let mut tags = Column::new()
     .spacing(2)
     .push(styles::text("Tags").vertical_alignment(VerticalAlignment::Center));
+  let mut progress = Column::new()
+    .spacing(2)
+    .push(styles::text("Progress").vertical_alignment(VerticalAlignment::Center));
+  let mut rules = Column::new()
+    .spacing(2)
+    .push(styles::text("Rules").vertical_alignment(VerticalAlignment::Center));
 
   for (idx, file) in files.into_iter().enumerate() {
     let file_md5 = file.get_md5();
     let file_name = file.truncated_file_name();
+    let file_progress = file.progress_view();
+    let file_status = file.status();
+    let file_format = file.format_view();
     let selected = match file_selection {
       FileSelection::None => false,
       FileSelection::Multiple(indices) => indices.contains(&idx),
     };
 
+    // Read before the name button borrows the file.
+    duration = duration.push(cell(file.duration_text(), selected));
+    resolution = resolution.push(cell(file.resolution_text(), selected));
+    frame_rate = frame_rate.push(cell(file.frame_rate_text(), selected));
+    codecs = codecs.push(cell(file.codecs_text(), selected));
+    bitrate = bitrate.push(cell(file.bitrate_text(), selected));
+    size = size.push(cell(
+      file.size.map(format_bytes).unwrap_or_default(),
+      selected,
+    ));
     file_names = file_names.push(
       Container::new(
         Button::new(&mut file.button, styles::text(file_name))
@@ -110,7 +415,12 @@ pub fn file_index<'a>(
       .style(styles::HoveredContainer::new(selected)),
     );
     status = status.push(
-      Container::new(styles::text(file.state.to_string()))
+      Container::new(styles::text(file_status))
+        .padding(2)
+        .style(styles::HoveredContainer::new(selected)),
+    );
+    format = format.push(
+      Container::new(file_format)
         .padding(2)
         .style(styles::HoveredContainer::new(selected)),
     );
@@ -120,7 +430,17 @@ pub fn file_index<'a>(
         .style(styles::HoveredContainer::new(selected)),
     );
     tags = tags.push(
-      Container::new(styles::text(&file.tags))
+      Container::new(styles::text(tags::join(&file.tags)))
+        .padding(2)
+        .style(styles::HoveredContainer::new(selected)),
+    );
+    progress = progress.push(
+      Container::new(file_progress)
+        .padding(2)
+        .style(styles::HoveredContainer::new(selected)),
+    );
+    rules = rules.push(
+      Container::new(styles::text(file.rules.join(", ")))
         .padding(2)
         .style(styles::HoveredContainer::new(selected)),
     );
@@ -129,8 +449,17 @@ pub fn file_index<'a>(
   let content = Row::new()
     .push(file_names)
     .push(status)
+    .push(progress)
+    .push(format)
+    .push(duration)
+    .push(resolution)
+    .push(frame_rate)
+    .push(codecs)
+    .push(bitrate)
+    .push(size)
     .push(md5)
     .push(tags)
+    .push(rules)
     .spacing(6)
     .width(Length::Fill);
 
@@ -152,11 +481,80 @@ impl File {
     }
   }
 
-  #[allow(dead_code)]
   pub fn file_name(&self) -> &str {
     self.path.file_name().unwrap().to_str().unwrap()
   }
 
+  /// Warns when the extension does not match the contents, e.g. an `.mp4`
+  /// that is really a WebM video.
+  pub fn format_mismatch(&self) -> Option<String> {
+    let format = self.format?;
+
+    if format.matches_extension(&self.path) {
+      return None;
+    }
+
+    match self.path.extension() {
+      Some(extension) => Some(format!(
+        "{} contents, not .{}",
+        format,
+        extension.to_string_lossy()
+      )),
+      None => Some(format!("{} contents, no extension", format)),
+    }
+  }
+
+  fn format_view(&self) -> Text {
+    match (self.format_mismatch(), self.format) {
+      (Some(mismatch), _) => styles::text(mismatch).color(Color::from_rgb(1.0, 0.4, 0.4)),
+      (None, Some(format)) => styles::text(format.to_string()),
+      (None, None) => styles::text("Unknown"),
+    }
+  }
+
+  fn bitrate(&self) -> Option<u64> {
+    self.metadata.bitrate(self.size?)
+  }
+
+  fn duration_text(&self) -> String {
+    self
+      .metadata
+      .duration
+      .map(format_duration)
+      .unwrap_or_default()
+  }
+
+  fn resolution_text(&self) -> String {
+    match (self.metadata.width, self.metadata.height) {
+      (Some(width), Some(height)) => format!("{}x{}", width, height),
+      _ => String::new(),
+    }
+  }
+
+  fn frame_rate_text(&self) -> String {
+    self
+      .metadata
+      .frame_rate
+      .map(|frame_rate| format!("{:.2}", frame_rate))
+      .unwrap_or_default()
+  }
+
+  fn codecs_text(&self) -> String {
+    let codecs: Vec<&str> = vec![&self.metadata.video_codec, &self.metadata.audio_codec]
+      .into_iter()
+      .filter_map(|codec| codec.as_deref())
+      .collect();
+
+    codecs.join(" / ")
+  }
+
+  fn bitrate_text(&self) -> String {
+    self
+      .bitrate()
+      .map(|bitrate| format!("{}/s", format_bits(bitrate)))
+      .unwrap_or_default()
+  }
+
   pub fn get_md5(&self) -> String {
     if self.md5.is_some() {
       format!("{:x}", self.md5.unwrap())
@@ -165,38 +563,195 @@ impl File {
     }
   }
 
+  fn progress_view<'a>(&self) -> Element<'a, Message> {
+    match (self.state, self.progress) {
+      (FileState::Uploading, Some(progress)) | (FileState::Paused, Some(progress)) => {
+        let eta = progress
+          .eta()
+          .map(format_duration)
+          .unwrap_or_else(|| "-".to_string());
+
+        Row::new()
+          .spacing(6)
+          .push(
+            ProgressBar::new(0.0..=100.0, progress.percent())
+              .width(Length::Units(100))
+              .height(Length::Units(14))
+              .style(styles::ProgressBar),
+          )
+          .push(styles::text(format!(
+            "{:.0}% {}/s ETA {}",
+            progress.percent(),
+            format_bytes(progress.speed() as u64),
+            eta
+          )))
+          .into()
+      }
+      (FileState::Analyzing, _) => match (self.hashed_bytes, self.size) {
+        (Some(bytes_hashed), Some(size)) if size > 0 => {
+          let percent = bytes_hashed as f32 / size as f32 * 100.0;
+
+          Row::new()
+            .spacing(6)
+            .push(
+              ProgressBar::new(0.0..=100.0, percent)
+                .width(Length::Units(100))
+                .height(Length::Units(14))
+                .style(styles::ProgressBar),
+            )
+            .push(styles::text(format!("{:.0}% hashed", percent)))
+            .into()
+        }
+        _ => styles::text("").into(),
+      },
+      (FileState::Failed, _) => match &self.error {
+        Some(error) => styles::text(error.to_string()).into(),
+        None => styles::text("").into(),
+      },
+      (FileState::Invalid, _) => match &self.invalid_reason {
+        Some(reason) => styles::text(reason).into(),
+        None => styles::text("").into(),
+      },
+      _ => styles::text("").into(),
+    }
+  }
+
+  /// The file's state, with a hint on what to do next if it failed.
+  fn status(&self) -> String {
+    match (self.state, &self.error) {
+      (FileState::Analyzing, _) if self.hashed_bytes.is_some() => "Hashing".to_owned(),
+      (FileState::Analyzing, _) => "Waiting to hash".to_owned(),
+      (FileState::Failed, Some(error)) => format!("Failed ({})", error.advice()),
+      (state, _) => state.to_string(),
+    }
+  }
+
   pub fn update(&mut self, message: FileMessage) {
     match message {
+      FileMessage::Hashed(bytes_hashed) => {
+        self.hashed_bytes = Some(bytes_hashed);
+      }
       FileMessage::Analyzed(analysis) => {
+        self.hashed_bytes = None;
+        self.cached_md5 = None;
         self.md5 = Some(analysis.md5);
-        self.state = FileState::Analyzed;
+        self.size = Some(analysis.size);
+        self.metadata = analysis.metadata;
+
+        match analysis.invalid_reason {
+          Some(reason) => {
+            self.state = FileState::Invalid;
+            self.invalid_reason = Some(reason);
+          }
+          None => self.state = FileState::Analyzed,
+        }
+      }
+      FileMessage::Uploaded(bytes_sent) => {
+        let total_bytes = self.size.unwrap_or_default();
+        let mut progress = self
+          .progress
+          .unwrap_or_else(|| UploadProgress::new(total_bytes));
+
+        // A retried upload starts over from the beginning of the file.
+        if bytes_sent < progress.bytes_sent {
+          progress = UploadProgress::new(total_bytes);
+        }
+
+        progress.bytes_sent = bytes_sent;
+        self.progress = Some(progress);
+      }
+      FileMessage::MultipartStarted(upload) => {
+        let total_bytes = self.size.unwrap_or_default();
+        let confirmed_bytes = upload.confirmed_bytes(total_bytes);
+
+        self.progress = Some(UploadProgress::resumed(total_bytes, confirmed_bytes));
+        self.multipart = Some(upload);
+      }
+      FileMessage::PartUploaded(part) => {
+        if let Some(upload) = self.multipart.as_mut() {
+          upload.parts.push(part);
+        }
       }
     }
   }
 
-  pub async fn analyze_file(id: u64, path: PathBuf) -> AnalyzeResult {
+  /// Hashes the file and reads its metadata, calling `on_progress` with the
+  /// number of bytes hashed after every read. A `known_md5`, e.g. from the
+  /// hash cache, is used instead of reading the whole file.
+  pub async fn analyze_file(
+    id: u64,
+    path: PathBuf,
+    known_md5: Option<md5::Digest>,
+    mut on_progress: impl FnMut(u64),
+  ) -> AnalyzeResult {
     use tokio::fs::File;
     use tokio::prelude::*;
 
-    let mut context = md5::Context::new();
-    let mut buffer = [0u8; 4 * 1024];
-    let mut file = File::open(path).await.map_err(|_| AnalyzeError::FileOpen)?;
-
-    loop {
-      let size = file
-        .read(&mut buffer[..])
+    // Fingerprinting and reading the metadata touch the disk synchronously,
+    // so they run on the blocking pool.
+    let before = {
+      let path = path.clone();
+      tokio::task::spawn_blocking(move || Fingerprint::of(&path))
         .await
-        .map_err(|_| AnalyzeError::FileRead)?;
+        .map_err(|_| AnalyzeError::FileRead)?
+    };
+    let mut file = File::open(&path)
+      .await
+      .map_err(|_| AnalyzeError::FileOpen)?;
+    let size = file
+      .metadata()
+      .await
+      .map_err(|_| AnalyzeError::FileRead)?
+      .len();
+
+    let digest = match known_md5 {
+      Some(digest) => digest,
+      None => {
+        let mut context = md5::Context::new();
+        let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+        let mut bytes_hashed = 0;
+
+        loop {
+          let size = file
+            .read(&mut buffer[..])
+            .await
+            .map_err(|_| AnalyzeError::FileRead)?;
 
-      if size == 0 {
-        break;
+          if size == 0 {
+            break;
+          }
+
+          context.consume(&buffer[..size]);
+          bytes_hashed += size as u64;
+          on_progress(bytes_hashed);
+        }
+
+        context.compute()
       }
+    };
 
-      context.consume(&buffer[..size]);
-    }
+    // Files we cannot read the metadata of are still uploaded, just
+    // without metadata to show; only broken containers are held back.
+    let (metadata, after) =
+      tokio::task::spawn_blocking(move || (metadata::read(&path), Fingerprint::of(&path)))
+        .await
+        .map_err(|_| AnalyzeError::FileRead)?;
+
+    let (metadata, invalid_reason) = match metadata {
+      Ok(metadata) => (metadata, None),
+      Err(MetadataError::Invalid(reason)) => (VideoMetadata::default(), Some(reason)),
+      Err(MetadataError::Io(_)) => (VideoMetadata::default(), None),
+    };
 
-    let digest = context.compute();
+    let fingerprint = before.filter(|before| after.as_ref() == Some(before));
 
-    Ok(FileAnalysis { id, md5: digest })
+    Ok(FileAnalysis {
+      id,
+      md5: digest,
+      size,
+      metadata,
+      invalid_reason,
+      fingerprint,
+    })
   }
 }
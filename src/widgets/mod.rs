@@ -1 +1,3 @@
 pub mod file;
+pub mod presets;
+pub mod tags;
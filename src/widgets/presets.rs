@@ -0,0 +1,107 @@
+use iced::{button, text_input, Button, Element, Length, Row, TextInput};
+
+use crate::config::TagPreset;
+use crate::styles;
+
+#[derive(Debug, Clone)]
+pub enum PresetMessage {
+  Apply(usize),
+  Delete(usize),
+  NameChanged(String),
+  /// Saves the tags shared by the selected files under the typed name.
+  Save,
+}
+
+#[derive(Debug, Default)]
+pub struct PresetPanel {
+  apply_buttons: Vec<button::State>,
+  delete_buttons: Vec<button::State>,
+  name: String,
+  name_input: text_input::State,
+  save_button: button::State,
+}
+
+impl PresetPanel {
+  pub fn set_name(&mut self, name: String) {
+    self.name = name;
+  }
+
+  pub fn take_name(&mut self) -> Option<String> {
+    let name = self.name.trim().to_owned();
+
+    if name.is_empty() {
+      None
+    } else {
+      self.name.clear();
+      Some(name)
+    }
+  }
+
+  pub fn view<'a>(&'a mut self, presets: &'a [TagPreset]) -> Element<'a, PresetMessage> {
+    self
+      .apply_buttons
+      .resize_with(presets.len(), Default::default);
+    self
+      .delete_buttons
+      .resize_with(presets.len(), Default::default);
+
+    let row = Row::new().spacing(6).push(styles::text("Presets:"));
+
+    let row = presets
+      .iter()
+      .zip(
+        self
+          .apply_buttons
+          .iter_mut()
+          .zip(self.delete_buttons.iter_mut()),
+      )
+      .enumerate()
+      .fold(
+        row,
+        |row, (idx, (preset, (apply_button, delete_button)))| {
+          // Ctrl+1 to Ctrl+9 apply the first nine presets.
+          let label = if idx < 9 {
+            format!("{} ({})", preset.name, idx + 1)
+          } else {
+            preset.name.clone()
+          };
+
+          row.push(
+            Row::new()
+              .push(
+                Button::new(apply_button, styles::text(label))
+                  .style(styles::Button::Chip)
+                  .padding(2)
+                  .on_press(PresetMessage::Apply(idx)),
+              )
+              .push(
+                Button::new(delete_button, styles::text("x"))
+                  .style(styles::Button::Chip)
+                  .padding(2)
+                  .on_press(PresetMessage::Delete(idx)),
+              ),
+          )
+        },
+      );
+
+    row
+      .push(
+        TextInput::new(
+          &mut self.name_input,
+          "Preset name",
+          &self.name,
+          PresetMessage::NameChanged,
+        )
+        .on_submit(PresetMessage::Save)
+        .style(styles::TextInput::Primary)
+        .width(Length::Units(150)),
+      )
+      .push(
+        Button::new(&mut self.save_button, styles::text("Save shared tags"))
+          .style(styles::Button::Transparent)
+          .padding(2)
+          .on_press(PresetMessage::Save),
+      )
+      .into()
+  }
+}
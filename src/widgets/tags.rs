@@ -0,0 +1,404 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use iced::{button, text_input, Button, Color, Column, Element, Length, Row, Text, TextInput};
+use iced_native::input::keyboard::KeyCode;
+
+use crate::api::{ApiError, TagSuggestion};
+use crate::styles;
+use crate::tags::{self, Tag};
+
+/// How long typing has to pause before tags are searched.
+pub const SEARCH_DELAY: Duration = Duration::from_millis(300);
+
+const MIN_QUERY_LENGTH: usize = 2;
+
+#[derive(Debug, Clone)]
+pub enum TagMessage {
+  InputChanged(String),
+  /// Adds the typed tags to every selected file, or completes the
+  /// highlighted suggestion.
+  Submit,
+  AddToAll(Tag),
+  Remove(Tag),
+  /// Typing paused; carries the input generation it paused at.
+  Search(u64),
+  Suggestions(String, Result<Vec<TagSuggestion>, ApiError>),
+  AcceptSuggestion(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct TagEditor {
+  input: text_input::State,
+  value: String,
+  error: Option<String>,
+  chips: Vec<Chip>,
+  selected_files: usize,
+  /// Bumped on every change to the input, to drop searches for stale input.
+  generation: u64,
+  suggestions: Vec<Suggestion>,
+  highlighted: Option<usize>,
+  /// Search results by query, kept for as long as the app runs.
+  cache: HashMap<String, Vec<TagSuggestion>>,
+}
+
+#[derive(Debug)]
+struct Suggestion {
+  tag: TagSuggestion,
+  button: button::State,
+}
+
+#[derive(Debug)]
+struct Chip {
+  tag: Tag,
+  count: usize,
+  add_button: button::State,
+  remove_button: button::State,
+}
+
+impl TagEditor {
+  /// Shows the tags of the selected files, those common to all of them
+  /// first.
+  pub fn load(&mut self, selection: &[Vec<Tag>]) {
+    let mut counts: BTreeMap<&Tag, usize> = BTreeMap::new();
+
+    for tags in selection {
+      for tag in tags.iter() {
+        *counts.entry(tag).or_default() += 1;
+      }
+    }
+
+    let mut chips: Vec<Chip> = counts
+      .into_iter()
+      .map(|(tag, count)| Chip {
+        tag: tag.clone(),
+        count,
+        add_button: button::State::default(),
+        remove_button: button::State::default(),
+      })
+      .collect();
+    chips.sort_by_key(|chip| chip.count != selection.len());
+
+    self.chips = chips;
+    self.selected_files = selection.len();
+  }
+
+  pub fn clear(&mut self) {
+    let cache = std::mem::take(&mut self.cache);
+
+    *self = Self {
+      cache,
+      ..Self::default()
+    };
+  }
+
+  /// Changes the input, showing cached suggestions for the partial tag at
+  /// its end. Returns the generation to search at once typing pauses, if
+  /// the partial tag is not cached yet.
+  pub fn set_input(&mut self, value: String) -> Option<u64> {
+    self.value = value;
+    self.error = None;
+    self.generation += 1;
+    self.highlighted = None;
+
+    match self.query() {
+      Some(query) => match self.cache.get(&query) {
+        Some(suggestions) => {
+          let suggestions = suggestions.clone();
+          self.show_suggestions(suggestions);
+          None
+        }
+        None => Some(self.generation),
+      },
+      None => {
+        self.suggestions.clear();
+        None
+      }
+    }
+  }
+
+  /// The partial tag to search for, normalized, if the input has not
+  /// changed since `generation`.
+  pub fn query_for(&self, generation: u64) -> Option<String> {
+    if generation == self.generation {
+      self.query()
+    } else {
+      None
+    }
+  }
+
+  fn query(&self) -> Option<String> {
+    let partial = self.value.rsplit(',').next().unwrap_or_default();
+
+    Tag::parse(partial)
+      .ok()
+      .map(String::from)
+      .filter(|query| query.chars().count() >= MIN_QUERY_LENGTH)
+  }
+
+  pub fn set_suggestions(&mut self, query: String, suggestions: Vec<TagSuggestion>) {
+    if self.query().as_ref() == Some(&query) {
+      self.show_suggestions(suggestions.clone());
+    }
+
+    self.cache.insert(query, suggestions);
+  }
+
+  fn show_suggestions(&mut self, suggestions: Vec<TagSuggestion>) {
+    self.suggestions = suggestions
+      .into_iter()
+      .map(|tag| Suggestion {
+        tag,
+        button: button::State::default(),
+      })
+      .collect();
+    self.highlighted = None;
+  }
+
+  /// Moves through the suggestions with the arrow keys and hides them with
+  /// Escape, but only while the input has focus.
+  pub fn key_pressed(&mut self, key_code: KeyCode) {
+    if !self.input.is_focused() || self.suggestions.is_empty() {
+      return;
+    }
+
+    match key_code {
+      KeyCode::Up => self.move_highlight(-1),
+      KeyCode::Down => self.move_highlight(1),
+      KeyCode::Escape => self.hide_suggestions(),
+      _ => {}
+    }
+  }
+
+  fn hide_suggestions(&mut self) {
+    self.suggestions.clear();
+    self.highlighted = None;
+  }
+
+  /// Moves the highlight `offset` suggestions down, or up if negative,
+  /// wrapping around at either end.
+  fn move_highlight(&mut self, offset: isize) {
+    let count = self.suggestions.len() as isize;
+    let next = match self.highlighted {
+      Some(idx) => idx as isize + offset,
+      None if offset > 0 => offset - 1,
+      None => count + offset,
+    };
+
+    self.highlighted = Some(next.rem_euclid(count) as usize);
+  }
+
+  pub fn accept_highlighted(&mut self) -> bool {
+    match self.highlighted {
+      Some(idx) => {
+        self.accept(idx);
+        true
+      }
+      None => false,
+    }
+  }
+
+  pub fn accept(&mut self, idx: usize) {
+    let name = match self.suggestions.get(idx) {
+      Some(suggestion) => suggestion.tag.name.clone(),
+      None => return,
+    };
+
+    let typed = match self.value.rfind(',') {
+      Some(comma) => format!("{}, ", &self.value[..comma]),
+      None => String::new(),
+    };
+
+    self.value = format!("{}{}, ", typed, name);
+    self.generation += 1;
+    self.hide_suggestions();
+  }
+
+  /// Takes the tags typed so far. Invalid input is kept so it can be fixed.
+  pub fn take_input(&mut self) -> Option<Vec<Tag>> {
+    match tags::parse_input(&self.value) {
+      Ok(tags) => {
+        self.set_input(String::new());
+        self.hide_suggestions();
+        Some(tags)
+      }
+      Err(err) => {
+        self.error = Some(err.to_string());
+        None
+      }
+    }
+  }
+
+  pub fn view(&mut self) -> Element<'_, TagMessage> {
+    let selected_files = self.selected_files;
+
+    let input = TextInput::new(
+      &mut self.input,
+      "Add tags, separated by commas",
+      &self.value,
+      TagMessage::InputChanged,
+    )
+    .on_submit(TagMessage::Submit)
+    .style(styles::TextInput::Primary)
+    .width(Length::Units(250));
+
+    let mut row = Row::new().spacing(6).push(input);
+
+    if let Some(error) = &self.error {
+      row = row.push(styles::text(error).color(Color::from_rgb(1.0, 0.4, 0.4)));
+    }
+
+    let highlighted = self.highlighted;
+
+    let row = self.chips.iter_mut().fold(row, |row, chip| {
+      let common = chip.count == selected_files;
+
+      let mut label = if common {
+        Button::new(&mut chip.add_button, styles::text(chip.tag.as_str()))
+          .style(styles::Button::Chip)
+      } else {
+        // Only on some of the selected files; pressing adds it to the rest.
+        Button::new(
+          &mut chip.add_button,
+          Text::new(format!("{} {}/{}", chip.tag, chip.count, selected_files)).size(14),
+        )
+        .style(styles::Button::PartialChip)
+      };
+
+      if !common {
+        label = label.on_press(TagMessage::AddToAll(chip.tag.clone()));
+      }
+
+      row.push(
+        Row::new().push(label.padding(2)).push(
+          Button::new(&mut chip.remove_button, styles::text("x"))
+            .style(styles::Button::Chip)
+            .padding(2)
+            .on_press(TagMessage::Remove(chip.tag.clone())),
+        ),
+      )
+    });
+
+    let suggestions = self.suggestions.iter_mut().enumerate().fold(
+      Column::new().spacing(2).width(Length::Units(250)),
+      |column, (idx, suggestion)| {
+        let mut label = format!("{} ({})", suggestion.tag.name, suggestion.tag.post_count);
+
+        if !suggestion.tag.aliases.is_empty() {
+          label = format!("{} <- {}", label, suggestion.tag.aliases.join(", "));
+        }
+
+        let style = if highlighted == Some(idx) {
+          styles::Button::Primary
+        } else {
+          styles::Button::Transparent
+        };
+
+        column.push(
+          Button::new(&mut suggestion.button, styles::text(label))
+            .style(style)
+            .width(Length::Fill)
+            .padding(2)
+            .on_press(TagMessage::AcceptSuggestion(idx)),
+        )
+      },
+    );
+
+    Column::new().spacing(4).push(row).push(suggestions).into()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn with_suggestions(input: &str, names: &[&str]) -> TagEditor {
+    let mut editor = TagEditor {
+      input: text_input::State::focused(),
+      ..TagEditor::default()
+    };
+
+    let generation = editor.set_input(input.to_owned()).unwrap();
+    let query = editor.query_for(generation).unwrap();
+    let suggestions = names
+      .iter()
+      .map(|name| TagSuggestion {
+        name: (*name).to_owned(),
+        post_count: 1,
+        aliases: Vec::new(),
+      })
+      .collect();
+    editor.set_suggestions(query, suggestions);
+    editor
+  }
+
+  #[test]
+  fn highlight_wraps_around() {
+    let mut editor = with_suggestions("lo", &["long_hair", "long_legs", "looping"]);
+    assert_eq!(editor.highlighted, None);
+
+    editor.key_pressed(KeyCode::Down);
+    assert_eq!(editor.highlighted, Some(0));
+    editor.key_pressed(KeyCode::Down);
+    editor.key_pressed(KeyCode::Down);
+    assert_eq!(editor.highlighted, Some(2));
+    editor.key_pressed(KeyCode::Down);
+    assert_eq!(editor.highlighted, Some(0));
+    editor.key_pressed(KeyCode::Up);
+    assert_eq!(editor.highlighted, Some(2));
+
+    let mut other = with_suggestions("lo", &["long_hair", "long_legs"]);
+    other.key_pressed(KeyCode::Up);
+    assert_eq!(other.highlighted, Some(1));
+  }
+
+  #[test]
+  fn keys_are_ignored_without_focus() {
+    let mut editor = with_suggestions("lo", &["long_hair", "long_legs"]);
+    editor.input = text_input::State::default();
+
+    editor.key_pressed(KeyCode::Down);
+    editor.key_pressed(KeyCode::Escape);
+    assert_eq!(editor.highlighted, None);
+    assert_eq!(editor.suggestions.len(), 2);
+  }
+
+  #[test]
+  fn escape_hides_suggestions() {
+    let mut editor = with_suggestions("lo", &["long_hair"]);
+    editor.key_pressed(KeyCode::Down);
+    editor.key_pressed(KeyCode::Escape);
+
+    assert!(editor.suggestions.is_empty());
+    assert_eq!(editor.highlighted, None);
+    assert!(!editor.accept_highlighted());
+  }
+
+  #[test]
+  fn accepting_replaces_the_partial_tag() {
+    let mut editor = with_suggestions("blue eyes, lo", &["long_hair", "long_legs"]);
+    editor.key_pressed(KeyCode::Down);
+    editor.key_pressed(KeyCode::Down);
+
+    assert!(editor.accept_highlighted());
+    assert_eq!(editor.value, "blue eyes, long_legs, ");
+    assert!(editor.suggestions.is_empty());
+
+    let tags = editor.take_input().unwrap();
+    let names: Vec<&str> = tags.iter().map(Tag::as_str).collect();
+    assert_eq!(names, vec!["blue_eyes", "long_legs"]);
+  }
+
+  #[test]
+  fn accepting_by_index_ignores_missing_suggestions() {
+    let mut editor = with_suggestions("lo", &["long_hair"]);
+
+    editor.accept(3);
+    assert_eq!(editor.value, "lo");
+
+    editor.accept(0);
+    assert_eq!(editor.value, "long_hair, ");
+    // Submitting without a highlight adds the typed tags instead.
+    assert!(!editor.accept_highlighted());
+  }
+}